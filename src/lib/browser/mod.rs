@@ -1,18 +1,17 @@
 pub mod prelude;
+mod test;
 
 use super::*;
-use druid::widget::{Container, Flex, Label, LineBreaking};
+use druid::widget::{Align, Container, Flex, Label, LineBreaking, MainAxisAlignment, Scroll};
 use druid::{
-    AppLauncher, Color, FontDescriptor, FontFamily, FontWeight, Widget, WidgetExt, WindowDesc,
+    AppLauncher, Color, FontDescriptor, FontFamily, FontWeight, UnitPoint, Widget, WidgetExt,
+    WindowDesc,
 };
 use prelude::Browser;
 use tokio::runtime::Runtime;
 
 const TEXT_COLOR: Color = Color::rgb8(0x00, 0x00, 0x00);
-
-const H1_FONT: FontDescriptor = FontDescriptor::new(FontFamily::SYSTEM_UI)
-    .with_weight(FontWeight::BOLD)
-    .with_size(48.0);
+const WINDOW_HEIGHT: f64 = 400.0;
 
 impl Browser {
     pub fn new(url: String) -> Self {
@@ -22,7 +21,8 @@ impl Browser {
     pub fn run(self) {
         let mut rt = Runtime::new().unwrap();
         let html = fetch_html(&self.url, &mut rt);
-        let app = WindowDesc::new(build_ui(&html)).window_size((700.0, 400.0));
+        let app =
+            WindowDesc::new(build_ui(&html)).window_size((DEFAULT_VIEWPORT_WIDTH, WINDOW_HEIGHT));
         AppLauncher::with_window(app).launch(()).expect("error");
     }
 }
@@ -31,41 +31,201 @@ fn fetch_html(url: &str, rt: &mut Runtime) -> String {
     rt.block_on(async { reqwest::get(url).await.unwrap().text().await.unwrap() })
 }
 
-fn build_ui(html: &str) -> impl Widget<()> {
-    let dom = DocumentObjectParser::new(html).parse();
-    let style = dom.extract_style();
-    let css = StyleSheetParser::new(&style).parse();
-    let render_object = RenderObject::build(dom, css).unwrap();
-    build_layout(&render_object).fix_height(1000.0)
+fn build_ui(html: &str) -> Scroll<(), impl Widget<()>> {
+    let content: Box<dyn Widget<()>> = match render_html(html) {
+        Ok(page) => box build_layout(&page.render_object, 1.0),
+        // nothing to show, e.g. the root element is `display: none`
+        Err(RenderError::NoRenderObject) => box Flex::column(),
+        Err(e) => box Label::new(e.to_string()).with_text_color(TEXT_COLOR),
+    };
+    // content determines its own height, and the page scrolls vertically when it is taller than the window
+    Scroll::new(content).vertical()
+}
+
+/// `opacity` is the product of `opacity` of ancestors, since druid can't fade a whole widget
+fn build_layout(render_object: &RenderObject, opacity: f64) -> impl Widget<()> {
+    let opacity = opacity * render_object.get_opacity();
+    let parent: Box<dyn Widget<()>> = match render_object.get_display() {
+        Display::Flex => box build_flex(render_object, opacity),
+        Display::Grid => box build_grid(render_object, opacity),
+        _ => box build_flow(render_object, opacity),
+    };
+    let parent = with_margin(parent, render_object);
+    let parent = with_color(box parent, render_object, opacity);
+    with_fixed_size(box parent, render_object)
+}
+
+/// Stack block children vertically, and lay inline children out in lines
+fn build_flow(render_object: &RenderObject, opacity: f64) -> impl Widget<()> {
+    render_object
+        .child_groups()
+        .into_iter()
+        .fold(Flex::column(), |parent, group| match group.as_slice() {
+            [child_object] => parent.with_child(to_child(
+                box build_layout(child_object, opacity),
+                render_object,
+                child_object,
+                opacity,
+            )),
+            _ => parent.with_child(build_line(render_object, &group, opacity)),
+        })
 }
 
-fn build_layout(render_object: &RenderObject) -> impl Widget<()> {
-    let parent = Flex::column();
-    let parent = render_object
+/// Lay children of `display: flex` out along flex-direction. They share the main axis by
+/// flex-grow, and a child with flex-grow 0 keeps its own size
+fn build_flex(render_object: &RenderObject, opacity: f64) -> impl Widget<()> {
+    let container = match render_object.get_flex_direction() {
+        FlexDirection::Row => Flex::row(),
+        FlexDirection::Column => Flex::column(),
+    };
+    render_object
         .children
         .iter()
-        .map(|child_object| (child_object, build_layout(child_object)))
-        .fold(parent, |parent, (child_object, child)| {
-            parent.with_child(to_child(box child, render_object, child_object))
-        });
-    let parent = with_margin(box parent, render_object);
-    let parent = with_color(box parent, render_object);
-    with_fixed_width(box parent, render_object)
-}
-
-fn with_color(parent: Box<dyn Widget<()>>, render_object: &RenderObject) -> impl Widget<()> {
-    use super::Color as CssColor;
-    let bg_color = match render_object.value(&DeclarationProperty::BackgroundColor) {
-        Some(DeclarationValue::Color(CssColor { r, g, b, .. })) => {
-            Some(Color::rgb8(*r as u8, *g as u8, *b as u8))
-        }
-        _ => None,
+        .zip(flex_grows(render_object))
+        .fold(container, |container, (child_object, grow)| {
+            let child = to_child(
+                box build_layout(child_object, opacity),
+                render_object,
+                child_object,
+                opacity,
+            );
+            if grow > 0.0 {
+                container.with_flex_child(child, grow)
+            } else {
+                container.with_child(child)
+            }
+        })
+        .main_axis_alignment(to_main_axis_alignment(render_object.get_justify_content()))
+        .must_fill_main_axis(true)
+}
+
+/// flex-grow of each child from `flex`. Without `flex`, children share the main axis equally
+/// unless justify-content distributes the free space
+fn flex_grows(render_object: &RenderObject) -> Vec<f64> {
+    let default_grow = match render_object.get_justify_content() {
+        JustifyContent::FlexStart => 1.0,
+        _ => 0.0,
     };
-    if let Some(bg_color_) = bg_color {
+    render_object
+        .children
+        .iter()
+        .map(|child_object| child_object.get_flex_grow().unwrap_or(default_grow))
+        .collect()
+}
+
+/// Place children row by row into columns of grid-template-columns.
+/// `fr` columns share the width left by fixed ones, and `auto` ones fit their content
+fn build_grid(render_object: &RenderObject, opacity: f64) -> impl Widget<()> {
+    let tracks = render_object.get_grid_template_columns();
+    render_object
+        .grid_rows()
+        .into_iter()
+        .fold(Flex::column(), |grid, row| {
+            let row = row
+                .iter()
+                .enumerate()
+                .fold(Flex::row(), |row, (i, child_object)| {
+                    let child = to_child(
+                        box build_layout(child_object, opacity),
+                        render_object,
+                        child_object,
+                        opacity,
+                    );
+                    match tracks.get(i) {
+                        Some(TrackSize::Fr(fr)) if *fr > 0.0 => {
+                            row.with_flex_child(child, *fr as f64)
+                        }
+                        Some(TrackSize::Length(length @ Length::Actual(..))) => {
+                            row.with_child(child.fix_width(render_object.to_px(length)))
+                        }
+                        _ => row.with_child(child),
+                    }
+                })
+                .must_fill_main_axis(true);
+            grid.with_child(row)
+        })
+}
+
+fn to_main_axis_alignment(justify_content: &JustifyContent) -> MainAxisAlignment {
+    match justify_content {
+        JustifyContent::FlexStart => MainAxisAlignment::Start,
+        JustifyContent::FlexEnd => MainAxisAlignment::End,
+        JustifyContent::Center => MainAxisAlignment::Center,
+        JustifyContent::SpaceBetween => MainAxisAlignment::SpaceBetween,
+        JustifyContent::SpaceAround => MainAxisAlignment::SpaceAround,
+    }
+}
+
+/// Lay consecutive inline children out side by side. Texts flex so that they wrap in the line
+fn build_line(
+    render_object: &RenderObject,
+    group: &[&RenderObject],
+    opacity: f64,
+) -> impl Widget<()> {
+    group
+        .iter()
+        .fold(Flex::row(), |line, child_object| {
+            let child = to_child(
+                box build_layout(child_object, opacity),
+                render_object,
+                child_object,
+                opacity,
+            );
+            if let Node::Text(_) = child_object.node {
+                line.with_flex_child(child, 1.0)
+            } else {
+                line.with_child(child)
+            }
+        })
+        .align_left()
+}
+
+fn with_color(
+    parent: Box<dyn Widget<()>>,
+    render_object: &RenderObject,
+    opacity: f64,
+) -> impl Widget<()> {
+    let bg_color = to_druid_color(
+        render_object,
+        &DeclarationProperty::BackgroundColor,
+        opacity,
+    );
+    let container = if let Some(bg_color_) = bg_color {
         parent.background(bg_color_)
     } else {
         // TODO: impl better
         Container::new(parent)
+    };
+    if let Some(radius) = render_object.get_border_radius() {
+        container.rounded(radius)
+    } else {
+        container
+    }
+}
+
+/// Map a color property (e.g. `background-color`, `color`) of the render object to druid Color,
+/// whose alpha is multiplied by opacity
+fn to_druid_color(
+    render_object: &RenderObject,
+    property: &DeclarationProperty,
+    opacity: f64,
+) -> Option<Color> {
+    match render_object.value(property) {
+        Some(DeclarationValue::Color(color)) => Some(color.with_opacity(opacity).to_druid()),
+        _ => None,
+    }
+}
+
+impl super::Color {
+    /// Convert to druid Color keeping the alpha channel
+    pub fn to_druid(&self) -> Color {
+        Color::rgba8(self.r as u8, self.g as u8, self.b as u8, self.a as u8)
+    }
+
+    /// Same color with alpha multiplied by opacity in 0..=1
+    fn with_opacity(&self, opacity: f64) -> Self {
+        let a = (self.a as f64 * opacity).round() as usize;
+        Self::new(self.r, self.g, self.b, a)
     }
 }
 
@@ -78,63 +238,114 @@ fn with_margin(parent: Box<dyn Widget<()>>, render_object: &RenderObject) -> imp
     parent.padding((margin_left, margin_top, margin_right, margin_bottom))
 }
 
-fn with_fixed_width(parent: Box<dyn Widget<()>>, render_object: &RenderObject) -> impl Widget<()> {
+/// Fix width/height when specified, otherwise the size follows the content
+fn with_fixed_size(parent: Box<dyn Widget<()>>, render_object: &RenderObject) -> impl Widget<()> {
     let parent: Box<dyn Widget<()>> = if let Some(width) = render_object.get_width() {
         box parent.fix_width(width)
     } else {
         box parent
     };
+    let parent: Box<dyn Widget<()>> = if let Some(height) = render_object.get_height() {
+        box parent.fix_height(height)
+    } else {
+        box parent
+    };
     parent
 }
 
+/// Align the text according to `text-align`
+fn align_text(label: impl Widget<()> + 'static, text_align: &TextAlign) -> Align<()> {
+    Align::new(to_unit_point(text_align), label)
+}
+
+/// Justify is aligned left as druid Label can't justify
+fn to_unit_point(text_align: &TextAlign) -> UnitPoint {
+    match text_align {
+        TextAlign::Left | TextAlign::Justify => UnitPoint::LEFT,
+        TextAlign::Center => UnitPoint::CENTER,
+        TextAlign::Right => UnitPoint::RIGHT,
+    }
+}
+
+/// Font of the text from the computed `font-weight` of the render object
+fn to_druid_font(render_object: &RenderObject) -> FontDescriptor {
+    FontDescriptor::new(FontFamily::SYSTEM_UI)
+        .with_weight(FontWeight::new(render_object.get_font_weight()))
+}
+
+/// Half of the space which `line-height` adds to the text, since druid Label doesn't have line height
+fn text_leading(parent_object: &RenderObject, child_object: &RenderObject) -> f64 {
+    if let Node::Text(_) = child_object.node {
+        let font_size = parent_object.get_font_size();
+        if let Some(line_height) = child_object.get_line_height(font_size) {
+            return ((line_height - font_size) / 2.0).max(0.0);
+        }
+    }
+    0.0
+}
+
 fn to_child(
     child: Box<dyn Widget<()>>,
     parent_object: &RenderObject,
     child_object: &RenderObject,
+    opacity: f64,
 ) -> impl Widget<()> {
+    let leading = text_leading(parent_object, child_object);
     let padding_left = child_object.get_length(&DeclarationProperty::MarginLeft);
-    let padding_top = child_object.get_length(&DeclarationProperty::MarginTop);
+    let padding_top = child_object.get_length(&DeclarationProperty::MarginTop) + leading;
     let padding_right = child_object.get_length(&DeclarationProperty::MarginRight);
-    let padding_bottom = child_object.get_length(&DeclarationProperty::MarginBottom);
+    let padding_bottom = child_object.get_length(&DeclarationProperty::MarginBottom) + leading;
+    let text_color =
+        to_druid_color(parent_object, &DeclarationProperty::Color, opacity).unwrap_or(TEXT_COLOR);
+    let font_size = parent_object.get_font_size();
+    let font = to_druid_font(parent_object);
+    let text_align = parent_object.get_text_align();
     (match &child_object.node {
         Node::Text(s) => match &parent_object.node {
             Node::Element(ref elem) => match elem.tag_name {
-                ElementTagName::H1 => Label::new(s.to_string())
-                    .with_font(H1_FONT)
-                    .with_text_size(24.0)
-                    .with_text_color(TEXT_COLOR)
-                    .padding((0.0, 8.0))
-                    .align_left(),
-                ElementTagName::A => Label::new(s.to_string())
-                    .with_text_color(Color::rgb8(0x00, 0x00, 0xff))
-                    .padding((0.0, 12.0))
-                    .align_left(),
-                ElementTagName::P => Label::new(s.to_string())
-                    .with_text_color(TEXT_COLOR)
-                    .with_line_break_mode(LineBreaking::WordWrap)
-                    .padding((0.0, 12.0))
-                    .align_left(),
-                _ => Label::new(s.to_string())
-                    .with_text_color(TEXT_COLOR)
-                    .with_line_break_mode(LineBreaking::WordWrap)
-                    .align_left(),
+                ElementTagName::H1 => align_text(
+                    Label::new(s.to_string())
+                        .with_font(font)
+                        .with_text_size(font_size)
+                        .with_text_color(text_color)
+                        .padding((0.0, 8.0)),
+                    text_align,
+                ),
+                ElementTagName::A => align_text(
+                    Label::new(s.to_string())
+                        .with_font(font)
+                        .with_text_size(font_size)
+                        .with_text_color(text_color)
+                        .padding((0.0, 12.0)),
+                    text_align,
+                ),
+                ElementTagName::P => align_text(
+                    Label::new(s.to_string())
+                        .with_font(font)
+                        .with_text_size(font_size)
+                        .with_text_color(text_color)
+                        .with_line_break_mode(LineBreaking::WordWrap)
+                        .padding((0.0, 12.0)),
+                    text_align,
+                ),
+                _ => align_text(
+                    Label::new(s.to_string())
+                        .with_font(font)
+                        .with_text_size(font_size)
+                        .with_text_color(text_color)
+                        .with_line_break_mode(LineBreaking::WordWrap),
+                    text_align,
+                ),
             },
             _ => child.align_left(),
         },
         Node::Element(ref elem) => match elem.tag_name {
-            ElementTagName::Div => child.center(),
-            ElementTagName::Body => child.fix_height(1000.0).center(),
-            ElementTagName::Html
-            | ElementTagName::Main
-            | ElementTagName::Article
-            | ElementTagName::P
-            | ElementTagName::H1
-            | ElementTagName::H2
-            | ElementTagName::H3
-            | ElementTagName::A => child.align_left(),
-            _ => Flex::column().align_left(),
+            ElementTagName::Div | ElementTagName::Body => child.center(),
+            // the child is laid out by build_layout according to its display, e.g. img, br and
+            // inline tags like b or code are in a line of build_line
+            _ => child.align_left(),
         },
-        _ => Flex::column().align_left(),
+        _ => child.align_left(),
     })
     .padding((padding_left, padding_top, padding_right, padding_bottom))
 }
@@ -0,0 +1,136 @@
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        build_ui, flex_grows, to_druid_color, to_druid_font, to_main_axis_alignment, to_unit_point,
+        WINDOW_HEIGHT,
+    };
+    use crate::lib::*;
+    use druid::widget::{MainAxisAlignment, Scroll};
+    use druid::{FontWeight, UnitPoint};
+
+    #[test]
+    fn test_build_ui_scrolls() {
+        let html = format!("<html><body>{}</body></html>", "<p>line</p>".repeat(200));
+        // the root scrolls the page, whose height is determined by its content
+        let _: Scroll<(), _> = build_ui(&html);
+        // nothing fixes the height, so the content is taller than the window
+        let page = render_html(&html).unwrap();
+        assert_eq!(page.render_object.get_height(), None);
+        let layout = page.render_object.layout(DEFAULT_VIEWPORT_WIDTH);
+        assert!(layout.height > WINDOW_HEIGHT);
+        assert_eq!(layout.children[0].children.len(), 200);
+    }
+
+    #[test]
+    fn test_to_druid_color() {
+        let page = render_html_with_css(
+            "<html><body><div>a</div></body></html>",
+            "div { background-color: rgba(255, 0, 0, 0.5); }",
+        )
+        .unwrap();
+        let body = &page.render_object.children[0];
+        let div = &body.children[0];
+        let color = to_druid_color(div, &DeclarationProperty::BackgroundColor, 1.0).unwrap();
+        assert_eq!(color.as_rgba8(), (0xff, 0x00, 0x00, 0x80));
+        // alpha is multiplied by opacity of ancestors
+        let color = to_druid_color(div, &DeclarationProperty::BackgroundColor, 0.5).unwrap();
+        assert_eq!(color.as_rgba8(), (0xff, 0x00, 0x00, 0x40));
+        assert!(to_druid_color(body, &DeclarationProperty::BackgroundColor, 1.0).is_none());
+    }
+
+    #[test]
+    fn test_link_color() {
+        let page =
+            render_html("<html><body><a href=\"/\">link</a><p>text</p></body></html>").unwrap();
+        let body = &page.render_object.children[0];
+        let link = to_druid_color(&body.children[0], &DeclarationProperty::Color, 1.0).unwrap();
+        assert_eq!(link.as_rgba8(), (0x00, 0x00, 0xff, 0xff));
+        let text = to_druid_color(&body.children[1], &DeclarationProperty::Color, 1.0).unwrap();
+        assert_eq!(text.as_rgba8(), (0x00, 0x00, 0x00, 0xff));
+    }
+
+    #[test]
+    fn test_inline_tags_share_a_line() {
+        let page = render_html(
+            "<html><body><p>a <b>b</b> <em>c</em> <code>d</code><img/></p></body></html>",
+        )
+        .unwrap();
+        let p = &page.render_object.children[0].children[0];
+        let groups = p.child_groups();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].len(), p.children.len());
+    }
+
+    #[test]
+    fn test_color_to_druid_keeps_alpha() {
+        // rgba(0,0,0,0.02) of the box-shadow of example.com
+        let color = Color::new(0x00, 0x00, 0x00, 0x05);
+        assert_eq!(color.to_druid().as_rgba8(), (0x00, 0x00, 0x00, 0x05));
+        let color = Color::new(0x38, 0x48, 0x8f, 0xff);
+        assert_eq!(color.to_druid().as_rgba8(), (0x38, 0x48, 0x8f, 0xff));
+    }
+
+    #[test]
+    fn test_to_druid_font() {
+        let page = render_html_with_css(
+            "<div><h1>a</h1><p>b</p><span>c</span></div>",
+            "p { font-weight: 300 } span { font-weight: bold }",
+        )
+        .unwrap();
+        let weights = page
+            .render_object
+            .children
+            .iter()
+            .map(|object| to_druid_font(object).weight)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            weights,
+            vec![FontWeight::BOLD, FontWeight::new(300), FontWeight::BOLD]
+        );
+        assert_eq!(
+            to_druid_font(&page.render_object).weight,
+            FontWeight::NORMAL
+        );
+    }
+
+    #[test]
+    fn test_to_unit_point() {
+        assert_eq!(to_unit_point(&TextAlign::Left), UnitPoint::LEFT);
+        assert_eq!(to_unit_point(&TextAlign::Center), UnitPoint::CENTER);
+        assert_eq!(to_unit_point(&TextAlign::Right), UnitPoint::RIGHT);
+        // druid Label can't justify
+        assert_eq!(to_unit_point(&TextAlign::Justify), UnitPoint::LEFT);
+    }
+
+    #[test]
+    fn test_flex_grows() {
+        let page = render_html_with_css(
+            "<div><section>a</section><section>b</section><section>c</section></div>",
+            "div { display: flex }",
+        )
+        .unwrap();
+        // three children share the container width
+        assert_eq!(flex_grows(&page.render_object), vec![1.0, 1.0, 1.0]);
+        let page = render_html_with_css(
+            "<div><section>a</section><p>b</p><section>c</section></div>",
+            "div { display: flex } p { flex: 2 }",
+        )
+        .unwrap();
+        assert_eq!(flex_grows(&page.render_object), vec![1.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn test_justify_content_space_between() {
+        let page = render_html_with_css(
+            "<div><section>a</section><section>b</section><section>c</section></div>",
+            "div { display: flex; justify-content: space-between }",
+        )
+        .unwrap();
+        // children keep their own size, and the free space is spread between them
+        assert_eq!(flex_grows(&page.render_object), vec![0.0, 0.0, 0.0]);
+        assert_eq!(
+            to_main_axis_alignment(page.render_object.get_justify_content()),
+            MainAxisAlignment::SpaceBetween
+        );
+    }
+}
@@ -1,13 +1,64 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
 use super::cssom::prelude::*;
-use super::dom::prelude::{Element, ElementTagName};
+use super::dom::prelude::{Element, ElementTagName, Node};
 
 pub mod prelude;
 mod test;
 
+/// Default styles of elements applied before the author's stylesheet
+const USER_AGENT_STYLE: &str = "
+h1 { font-size: 2em; font-weight: bold; }
+h2 { font-size: 1.5em; font-weight: bold; }
+h3 { font-size: 1.17em; font-weight: bold; }
+a, span, b, i, em, strong, code, img { display: inline; }
+a { color: blue; }
+section, nav, header, footer, aside, figure, figcaption { display: block; }
+figure { margin: 1em 40px; }
+button { display: inline-block; }
+";
+
+fn user_agent_stylesheet() -> StyleSheet {
+    StyleSheetParser::new(USER_AGENT_STYLE)
+        .parse()
+        .expect("Cannot parse user agent style")
+}
+
+/// URL of `@import` without `url()` and quotes, e.g. url("theme.css") screen → theme.css
+fn import_url(target: &str) -> Option<&str> {
+    let target = target.trim();
+    let url = match target.strip_prefix("url(") {
+        Some(rest) => rest.split(')').next()?.trim(),
+        // media query can follow, e.g. "print.css" print
+        None => target.split_whitespace().next()?,
+    };
+    let url = url.trim_matches(|ch| ch == '"' || ch == '\'');
+    (!url.is_empty()).then_some(url)
+}
+
+/// Selectors of a rule for error messages, e.g. `div > p, h1`
+fn selectors_text(selectors: &[Selector]) -> String {
+    selectors
+        .iter()
+        .map(|selector| selector.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 impl<'a> StyleSheetParser<'a> {
+    /// A leading byte order mark is ignored
     pub fn new(input: &'a str) -> Self {
+        let input = input.strip_prefix('\u{feff}').unwrap_or(input);
         Self {
             input: input.chars().peekable(),
+            len: input.chars().count(),
+            errors: vec![],
+            fetcher: None,
+            strict: false,
+            warnings: vec![],
         }
     }
 
@@ -23,86 +74,357 @@ impl<'a> StyleSheetParser<'a> {
     ///     display: block;
     /// }
     /// "#;
-    /// let mut style_sheet = StyleSheetParser::new(css).parse();
+    /// let mut style_sheet = StyleSheetParser::new(css).parse().unwrap();
     /// ```
-    pub fn parse(&mut self) -> StyleSheet {
+    ///
+    /// A declaration which has an error is skipped to the next `;`, and a rule whose selector has
+    /// an error is skipped to its closing `}`. The errors are kept in `errors`.
+    /// Err is returned only when the input ends in a rule
+    ///
+    /// Rules of `@import` are put before the rules of the stylesheet, so that they cascade first
+    pub fn parse(&mut self) -> Result<StyleSheet, CssParseError> {
+        let mut imported = vec![];
         let mut rules = vec![];
-        let mut media_query: Option<String> = None;
+        let mut charset = None;
         loop {
-            if self.peek().is_none() {
-                break;
+            match self.peek() {
+                None => break,
+                Some('@') => {
+                    self.bump();
+                    let at_rule = self.consume(&|ch| !matches!(ch, '{' | ';'));
+                    if let Some(target) = at_rule.strip_prefix("import") {
+                        self.skip_at_rule_body();
+                        imported.extend(self.parse_import(target));
+                        continue;
+                    }
+                    if let Some(name) = at_rule.strip_prefix("charset") {
+                        self.skip_at_rule_body();
+                        // only the one at the top of the stylesheet declares the encoding
+                        if rules.is_empty() && imported.is_empty() && charset.is_none() {
+                            charset = Some(name.trim().trim_matches(['"', '\'']).to_string());
+                        }
+                        continue;
+                    }
+                    match at_rule.strip_prefix("media") {
+                        Some(query) => {
+                            if let Err(e) = self.skip_next_ch(&'{') {
+                                self.errors.push(e);
+                                self.skip_at_rule_body();
+                                continue;
+                            }
+                            let media = StyleSheetParser::new(query).parse_media_query();
+                            loop {
+                                match self.peek() {
+                                    Some('}') => {
+                                        self.bump();
+                                        break;
+                                    }
+                                    Some(_) => {
+                                        let rule = self.parse_rule();
+                                        if let Some(mut rule) = self.recover(rule)? {
+                                            rule.media = Some(media.clone());
+                                            rules.push(rule);
+                                        }
+                                    }
+                                    None => {
+                                        let media_rule = format!("@{}", at_rule.trim());
+                                        return Err(CssParseError::UnterminatedRule(media_rule));
+                                    }
+                                }
+                            }
+                        }
+                        // e.g. @font-face and @keyframes are not supported
+                        None => self.skip_at_rule_body(),
+                    }
+                }
+                Some(_) => {
+                    let rule = self.parse_rule();
+                    if let Some(rule) = self.recover(rule)? {
+                        rules.push(rule);
+                    }
+                }
             }
-            media_query = match self.peek().unwrap() {
-                // TODO: impl better
-                '@' => {
+        }
+        imported.extend(rules);
+        let mut stylesheet = StyleSheet::new(imported);
+        stylesheet.charset = charset;
+        Ok(stylesheet)
+    }
+
+    /// Set the function which fetches the stylesheet of `@import` by its URL.
+    /// Imports are ignored without it
+    ///
+    /// e.g.
+    ///   StyleSheetParser::new(r#"@import url("theme.css");"#)
+    ///       .with_import_fetcher(|url| std::fs::read_to_string(url).ok())
+    pub fn with_import_fetcher(mut self, fetcher: impl FnMut(&str) -> Option<String> + 'a) -> Self {
+        self.fetcher = Some(box (fetcher));
+        self
+    }
+
+    /// In strict mode, unknown properties are recorded in `warnings` to catch typos. They are
+    /// parsed as `Other` either way
+    ///
+    /// e.g. `colr: red` warns, but `--main-color: red` and `-webkit-appearance: none` don't
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Rules of the stylesheet which `@import` refers to. It is ignored without fetcher, or when
+    /// the fetcher cannot find it
+    ///
+    /// e.g. url("theme.css"), url(theme.css), "theme.css"
+    fn parse_import(&mut self, target: &str) -> Vec<Rule> {
+        let (fetcher, url) = match (self.fetcher.as_mut(), import_url(target)) {
+            (Some(fetcher), Some(url)) => (fetcher, url),
+            _ => return vec![],
+        };
+        let content = match fetcher(url) {
+            Some(content) => content,
+            None => return vec![],
+        };
+        // imports in the imported stylesheet are fetched by the same fetcher
+        let mut parser = StyleSheetParser::new(&content)
+            .with_import_fetcher(|url| fetcher(url))
+            .with_strict(self.strict);
+        let stylesheet = parser.parse();
+        self.errors.append(&mut parser.errors);
+        self.warnings.append(&mut parser.warnings);
+        match stylesheet {
+            Ok(stylesheet) => stylesheet.rules,
+            Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    /// Errors of declarations and rules skipped by `parse` and `parse_inline`
+    pub fn errors(&self) -> &[CssParseError] {
+        &self.errors
+    }
+
+    /// Warnings of strict mode, see `with_strict`
+    pub fn warnings(&self) -> &[CssParseWarning] {
+        &self.warnings
+    }
+
+    /// Keep the error of the rule which `parse_rule` has skipped, so that parsing goes on.
+    /// Errors at the end of input are returned as is
+    fn recover<T>(&mut self, result: Result<T, CssParseError>) -> Result<Option<T>, CssParseError> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(
+                e @ (CssParseError::UnterminatedRule(_) | CssParseError::UnterminatedSelector(_)),
+            ) => Err(e),
+            Err(e) => {
+                self.errors.push(e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Parse the condition of `@media`, i.e. comma separated groups of conditions joined by `and`
+    ///
+    /// e.g.
+    ///   screen and (min-width: 400px) and (max-width: 700px), print
+    ///   not print and (max-width: 700px) → [Not([print, (max-width: 700px)])]
+    fn parse_media_query(&mut self) -> MediaQuery {
+        let mut queries = vec![];
+        let mut conditions = vec![];
+        // `not` negates the whole group
+        let mut negated = false;
+        let group = |conditions: Vec<MediaCondition>, negated: bool| match negated {
+            true => vec![MediaCondition::Not(conditions)],
+            false => conditions,
+        };
+        while let Some(&ch) = self.peek() {
+            match ch {
+                ',' => {
                     self.bump();
-                    let media_query = self.consume(&|ch| !matches!(ch, '{'));
-                    self.skip_next_ch(&'{');
-                    Some(media_query)
+                    queries.push(group(std::mem::take(&mut conditions), negated));
+                    negated = false;
                 }
-                _ => None,
-            };
-            let rule = self.parse_rule();
-            if media_query.is_some() {
+                '(' => {
+                    self.bump();
+                    conditions.push(self.parse_media_condition());
+                }
+                _ => match self.consume_identifier().as_str() {
+                    // `only` only hides the query from legacy browsers
+                    "and" | "only" => {}
+                    "not" => negated = true,
+                    // skip unexpected character
+                    "" => self.bump(),
+                    media_type => {
+                        conditions.push(MediaCondition::MediaType(media_type.to_lowercase()))
+                    }
+                },
+            }
+        }
+        queries.push(group(conditions, negated));
+        MediaQuery { queries }
+    }
+
+    /// Parse a media feature after `(` to the closing `)`
+    fn parse_media_condition(&mut self) -> MediaCondition {
+        let feature = self.consume_identifier().to_lowercase();
+        let condition = match (feature.as_str(), self.peek()) {
+            ("min-width" | "max-width", Some(':')) => {
+                self.bump();
                 self.skip_whitespace();
-                self.skip_next_ch(&'}');
+                match (feature.as_str(), self.parse_declaration_actual_length()) {
+                    ("min-width", Ok(length)) => MediaCondition::MinWidth(length),
+                    (_, Ok(length)) => MediaCondition::MaxWidth(length),
+                    // never matches
+                    (_, Err(_)) => MediaCondition::Other(feature.clone()),
+                }
             }
-            // TODO: impl better. now ignore media query
-            if media_query.is_none() {
-                rules.push(rule);
+            (_, Some(':')) => {
+                self.bump();
+                let value = self.consume(&|ch| *ch != ')');
+                MediaCondition::Other(format!("{}: {}", feature, value.trim()))
             }
+            _ => MediaCondition::Other(feature),
+        };
+        if let Some(')') = self.peek() {
+            self.bump();
         }
-        StyleSheet::new(rules, media_query)
+        condition
     }
 
     /// Parse one CSS Rule, this used in `parse`
-    fn parse_rule(&mut self) -> Rule {
-        use super::DeclarationProperty::*;
+    ///
+    /// On an error in selectors, the rest of the rule is skipped to its closing `}`
+    fn parse_rule(&mut self) -> Result<Rule, CssParseError> {
         let mut selectors = vec![];
         loop {
-            match self.peek().unwrap() {
-                '{' => {
+            match self.peek() {
+                Some('{') => {
                     self.bump();
                     break;
                 }
-                _ => selectors.push(self.parse_selector()),
+                Some(_) => match self.parse_selector() {
+                    Ok(selector) => selectors.push(selector),
+                    Err(e) => {
+                        self.skip_rule_rest();
+                        return Err(e);
+                    }
+                },
+                None => {
+                    let selectors = selectors_text(&selectors);
+                    return Err(CssParseError::UnterminatedSelector(selectors));
+                }
             }
         }
+        let declarations = self.parse_declaration_block();
+        match self.peek() {
+            Some('}') => self.bump(),
+            _ => return Err(CssParseError::UnterminatedRule(selectors_text(&selectors))),
+        }
+        Ok(Rule::new(selectors, declarations))
+    }
+
+    /// Parse declarations of inline style, i.e. `style` attribute of element
+    ///
+    /// e.g.
+    ///   color:#f00;margin:4px → [Color, MarginTop, MarginRight, MarginBottom, MarginLeft]
+    ///
+    /// A declaration which has an error is skipped, as it is by `parse`
+    pub fn parse_inline(&mut self) -> Vec<Declaration> {
+        let mut declarations = self.parse_declaration_block();
+        // `}` which closes nothing
+        while self.peek().is_some() {
+            let e = self.unexpected();
+            self.errors.push(e);
+            self.bump();
+            declarations.extend(self.parse_declaration_block());
+        }
+        declarations
+    }
+
+    /// Parse declarations until `}` or EOF, this used in `parse_rule` and `parse_inline`
+    ///
+    /// A declaration which has an error is skipped to the next `;`, and the error is kept in
+    /// `errors`
+    ///
+    /// e.g.
+    ///   color: ###; display: block → [Display]
+    fn parse_declaration_block(&mut self) -> Vec<Declaration> {
         let mut declarations = vec![];
         loop {
-            match self.peek().unwrap() {
-                '}' => {
-                    self.bump();
-                    break;
-                }
-                _ => {
-                    // use crate::prelude::DeclarationProperty::*;
-                    let property = DeclarationProperty::from(self.consume_identifier().as_str());
-                    self.skip_next_ch(&':');
-                    match property {
-                        Margin | Padding => declarations.extend(self.parse_declarations(property)),
-                        _ => declarations.push(self.parse_declaration(property)),
+            match self.peek() {
+                Some('}') | None => break,
+                Some(';') => self.bump(),
+                Some(_) => {
+                    match self.parse_declaration_item() {
+                        Ok(parsed) => declarations.extend(parsed),
+                        Err(e) => self.errors.push(e),
                     }
+                    // the rest of the declaration which has an error, e.g. `: 0` of `top left: 0`
+                    self.consume_raw_value();
                 }
             }
         }
-        Rule::new(selectors, declarations)
+        declarations
+    }
+
+    /// Parse one declaration in a declaration block, e.g. `margin: 0 auto !important`
+    fn parse_declaration_item(&mut self) -> Result<Vec<Declaration>, CssParseError> {
+        use super::DeclarationProperty::*;
+        let start = self.input.clone();
+        let name = self.consume_identifier();
+        let property = DeclarationProperty::from(name.as_str());
+        // custom properties and vendor prefixed ones are unknown by design
+        if self.strict && matches!(property, Other(_)) && !name.starts_with('-') {
+            let position = self.position_at(&start);
+            self.warnings
+                .push(CssParseWarning::UnknownProperty(name.clone(), position));
+        }
+        self.skip_next_ch(&':')?;
+        let raw_value = self.consume_raw_value();
+        let (value, important) = split_important(&raw_value);
+        let mut parser = StyleSheetParser::new(value);
+        let parsed = match property {
+            Margin | Padding | Font | BorderRadius => parser.parse_declarations(property),
+            _ => parser.parse_declaration(property).map(|d| vec![d]),
+        }
+        .map_err(|_| CssParseError::InvalidDeclaration(name, self.position_at(&start)))?;
+        Ok(parsed
+            .into_iter()
+            .map(|mut declaration| {
+                declaration.important = important;
+                declaration
+            })
+            .collect())
     }
 
     /// Parse Selector from css rule, this used in `parse_rule`
-    fn parse_selector(&mut self) -> Selector {
-        let selector = self.parse_one_selector();
-        if let Some(',') = self.input.peek() {
+    ///
+    /// A comma ends the selector even after a combinator, so the next one starts fresh
+    ///
+    /// e.g.
+    ///   div > p, h1 + span → Child(div, p), and then Adjacent(h1, span) by the next call
+    fn parse_selector(&mut self) -> Result<Selector, CssParseError> {
+        let selector = self.parse_one_selector()?;
+        // whitespace can be between selector and comma, e.g. `div > p , h1`
+        if let Some(',') = self.peek() {
             self.bump()
         };
-        selector
+        Ok(selector)
     }
 
     /// Parse one css selector, this used in `parse_selector`
-    fn parse_one_selector(&mut self) -> Selector {
+    fn parse_one_selector(&mut self) -> Result<Selector, CssParseError> {
+        let left = self.parse_compound_selector()?;
+        self.parse_sibling_selector(left)
+    }
+
+    /// Parse selector without combinators, e.g. `div.note:link`
+    fn parse_compound_selector(&mut self) -> Result<Selector, CssParseError> {
         let left = match self.peek() {
             Some('a'..='z' | 'A'..='Z' | '0'..='9') => {
-                let tag_name = self.consume_identifier();
+                let tag_name = self.consume_selector_identifier()?;
                 Some(Selector::Tag(ElementTagName::from(tag_name.as_ref())))
             }
             _ => None,
@@ -115,81 +437,146 @@ impl<'a> StyleSheetParser<'a> {
     /// e.g.
     ///   .box  → Selector::Class(None, "box".to_string()))
     ///   p#box → Selector::Id(Some(box (Selector::Tag(P))), "box".to_string()),
-    fn parse_class_selector(&mut self, left: Option<Selector>) -> Selector {
-        match self.peek() {
+    fn parse_class_selector(&mut self, left: Option<Selector>) -> Result<Selector, CssParseError> {
+        // whitespace is not skipped, since `div .box` is a descendant selector
+        match self.input.peek() {
             Some('.') => {
                 self.input.next();
-                let class = self.consume_identifier();
+                let class = self.consume_selector_identifier()?;
                 let left = match left {
                     Some(selector) => Selector::Class(Some(box (selector)), class),
                     None => Selector::Class(None, class),
                 };
-                self.parse_sibling_selector(Some(left))
+                self.parse_class_selector(Some(left))
             }
             Some('#') => {
                 self.input.next();
-                let id = self.consume_identifier();
+                let id = self.consume_selector_identifier()?;
                 let left = match left {
                     Some(selector) => Selector::Id(Some(box (selector)), id),
                     None => Selector::Id(None, id),
                 };
-                self.parse_sibling_selector(Some(left))
+                self.parse_class_selector(Some(left))
             }
-            Some('+' | '>') => self.parse_sibling_selector(left),
             Some(':') => {
                 self.input.next();
-                let pseudo_class = PseudoClass::from(self.consume_identifier().as_str());
+                let pseudo_class = self.parse_pseudo_class()?;
                 let left = match left {
                     Some(selector) => Selector::Pseudo(Some(box (selector)), pseudo_class),
                     None => Selector::Pseudo(None, pseudo_class), // TODO: このケース存在するのか？
                 };
-                self.parse_sibling_selector(Some(left))
+                self.parse_class_selector(Some(left))
             }
-            _ => left.unwrap(),
+            _ => left.ok_or_else(|| self.unexpected()),
         }
     }
 
-    /// parse_class_selector内に入れることができるが、可読性のため別けた
+    /// Parse pseudo-class after `:`. The argument of `:not` is a compound selector
     ///
     /// e.g.
-    ///   head > div > p
-    ///   Selector::Child(
-    ///   box (Selector::Tag(Head)),
-    ///   box (Selector::Child(box (Selector::Tag(Div)), box (Selector::Tag(P)))),
-    fn parse_sibling_selector(&mut self, left: Option<Selector>) -> Selector {
-        match self.input.peek() {
+    ///   link        → PseudoClass::Link
+    ///   not(.note)  → PseudoClass::Not(box (Selector::Class(None, "note".to_string())))
+    fn parse_pseudo_class(&mut self) -> Result<PseudoClass, CssParseError> {
+        let name = self.consume_selector_identifier()?;
+        match (name.as_str(), self.input.peek()) {
+            ("not", Some('(')) => {
+                self.input.next();
+                self.skip_whitespace();
+                let selector = self.parse_compound_selector()?;
+                self.skip_whitespace();
+                self.skip_next_ch(&')')?;
+                Ok(PseudoClass::Not(box (selector)))
+            }
+            _ => Ok(PseudoClass::from(name.as_str())),
+        }
+    }
+
+    /// Parse combinators following the compound selector. Combinators are left associative,
+    /// so the rightmost compound selector is the one which the element must match
+    ///
+    /// e.g.
+    ///   head > div p
+    ///   Selector::Descendant(
+    ///   box (Selector::Child(box (Selector::Tag(Head)), box (Selector::Tag(Div)))),
+    ///   box (Selector::Tag(P)))
+    fn parse_sibling_selector(&mut self, left: Selector) -> Result<Selector, CssParseError> {
+        let spaced = matches!(self.input.peek(), Some(ch) if ch.is_whitespace());
+        match self.peek() {
             Some('>') => {
                 self.input.next();
-                let right = self.parse_one_selector();
-                match left {
-                    Some(selector) => {
-                        let left = Selector::Child(box (selector), box (right));
-                        self.parse_sibling_selector(Some(left))
-                    }
-                    None => panic!("Cannot parse right selector"),
-                }
+                let right = self.parse_compound_selector()?;
+                self.parse_sibling_selector(Selector::Child(box (left), box (right)))
             }
             Some('+') => {
                 self.input.next();
-                let right = self.parse_one_selector();
-                match left {
-                    Some(selector) => {
-                        let left = Selector::Adjacent(box (selector), box (right));
-                        self.parse_sibling_selector(Some(left))
-                    }
-                    None => panic!("Cannot parse right selector"),
-                }
+                let right = self.parse_compound_selector()?;
+                self.parse_sibling_selector(Selector::Adjacent(box (left), box (right)))
+            }
+            Some('~') => {
+                self.input.next();
+                let right = self.parse_compound_selector()?;
+                let left = Selector::GeneralSibling(box (left), box (right));
+                self.parse_sibling_selector(left)
             }
-            _ => left.unwrap(),
+            // whitespace between compound selectors is the descendant combinator
+            Some('a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '#' | ':') if spaced => {
+                let right = self.parse_compound_selector()?;
+                self.parse_sibling_selector(Selector::Descendant(box (left), box (right)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    /// Identifier of tag, class, id and pseudo-class in selector, e.g. `box` of `.box`
+    ///
+    /// Unlike `consume_identifier`, the following whitespace is left for the descendant combinator
+    fn consume_selector_identifier(&mut self) -> Result<String, CssParseError> {
+        let mut ident = String::new();
+        while let Some(ch) = self
+            .input
+            .next_if(|ch| matches!(ch, '0'..='9' | 'a'..='z' | 'A'..='Z' | '_' | '-'))
+        {
+            ident.push(ch);
+        }
+        if ident.is_empty() {
+            return Err(CssParseError::ExpectedIdentifier(self.position()));
         }
+        Ok(ident)
     }
 
-    fn parse_declarations(&mut self, property: DeclarationProperty) -> Vec<Declaration> {
+    /// Error for the next character which cannot be parsed
+    fn unexpected(&mut self) -> CssParseError {
+        match self.peek() {
+            Some(&ch) => CssParseError::UnexpectedChar(ch, self.position()),
+            None => CssParseError::UnexpectedEof,
+        }
+    }
+
+    /// Offset in characters of the next character from the start of input
+    fn position(&self) -> usize {
+        self.position_at(&self.input)
+    }
+
+    /// Offset in characters of the next character of `input`, a copy of the input taken before.
+    /// Counting the rest of input is O(n), so positions are counted only for errors
+    fn position_at(&self, input: &Peekable<Chars<'a>>) -> usize {
+        self.len - input.clone().count()
+    }
+
+    fn parse_declarations(
+        &mut self,
+        property: DeclarationProperty,
+    ) -> Result<Vec<Declaration>, CssParseError> {
         use super::DeclarationProperty::*;
         match property {
             Margin => self.parse_declaration_margin(),
             Padding => self.parse_declaration_padding(),
-            _ => panic!("Cannot parse declarations"),
+            Font => self.parse_declaration_font(),
+            BorderRadius => self.parse_declaration_border_radius(),
+            _ => Err(CssParseError::InvalidDeclaration(
+                property.to_string(),
+                self.position(),
+            )),
         }
     }
 
@@ -198,32 +585,56 @@ impl<'a> StyleSheetParser<'a> {
     /// e.g.
     ///   margin: auto; → Declaration::new(Margin, Value::Other("auto".to_string()))
     ///   padding: 10.5px; →  Declaration::new(Padding, Value::Length(10.5, Unit::Px))
-    fn parse_declaration(&mut self, property: DeclarationProperty) -> Declaration {
+    fn parse_declaration(
+        &mut self,
+        property: DeclarationProperty,
+    ) -> Result<Declaration, CssParseError> {
         use super::DeclarationProperty::*;
         let declaration = match property {
             MarginLeft | MarginRight | MarginTop | MarginBottom | PaddingLeft | PaddingRight
-            | PaddingTop | PaddingBottom | Width | Height | BorderRadius => {
-                self.parse_declaration_length(property)
-            }
-            FontFamily => self.parse_font_family(),
-            BoxShadow => self.parse_declaration_box_shadow(),
-            Color | BackgroundColor => self.parse_declaration_color(property),
+            | PaddingTop | PaddingBottom | Width | Height | MinWidth | MaxWidth | MinHeight
+            | MaxHeight | FontSize => self.parse_declaration_length(property)?,
+            BorderTopLeftRadius
+            | BorderTopRightRadius
+            | BorderBottomRightRadius
+            | BorderBottomLeftRadius => self.parse_declaration_length(property)?,
+            FontFamily => self.parse_font_family()?,
+            BoxShadow => self.parse_declaration_box_shadow()?,
+            Color | BackgroundColor => self.parse_declaration_color(property)?,
             Display => self.parse_declaration_display(),
             TextDecoration => self.parse_declaration_text_decoration(),
-            Other(s) => self.parse_declaration_other(s),
-            _ => panic!("Cannot parse declaration"),
+            LineHeight => self.parse_declaration_line_height()?,
+            FontWeight => self.parse_declaration_font_weight()?,
+            TextAlign => self.parse_declaration_text_align(),
+            Opacity => self.parse_declaration_opacity()?,
+            Overflow => self.parse_declaration_overflow(),
+            Visibility => self.parse_declaration_visibility(),
+            Position => self.parse_declaration_position(),
+            Flex => self.parse_declaration_flex()?,
+            FlexDirection => self.parse_declaration_flex_direction(),
+            JustifyContent => self.parse_declaration_justify_content(),
+            GridTemplateColumns => self.parse_declaration_grid_template_columns()?,
+            Content => self.parse_declaration_content()?,
+            Transition | Animation | Other(_) => self.parse_declaration_raw(property),
+            // shorthands, which `parse_declarations` expands
+            _ => {
+                return Err(CssParseError::InvalidDeclaration(
+                    property.to_string(),
+                    self.position(),
+                ))
+            }
         };
-        self.skip_next_ch(&';');
-        declaration
+        self.skip_declaration_end()?;
+        Ok(declaration)
     }
 
     // TODO: impl better
-    fn parse_declaration_box_shadow(&mut self) -> Declaration {
-        let offset_x = self.parse_declaration_actual_length();
-        let offset_y = self.parse_declaration_actual_length();
-        let blur_radius = self.parse_declaration_actual_length();
-        let spread_radius = self.parse_declaration_actual_length();
-        let color = self.parse_rgba();
+    fn parse_declaration_box_shadow(&mut self) -> Result<Declaration, CssParseError> {
+        let offset_x = self.parse_declaration_actual_length()?;
+        let offset_y = self.parse_declaration_actual_length()?;
+        let blur_radius = self.parse_declaration_actual_length()?;
+        let spread_radius = self.parse_declaration_actual_length()?;
+        let color = self.parse_rgba()?;
         let box_shadow = BoxShadow {
             offset_x,
             offset_y,
@@ -231,67 +642,224 @@ impl<'a> StyleSheetParser<'a> {
             spread_radius,
             color,
         };
-        Declaration::new(
+        Ok(Declaration::new(
             DeclarationProperty::BoxShadow,
             DeclarationValue::BoxShadow(box_shadow),
-        )
+        ))
     }
 
-    // TODO: impl better
-    fn parse_font_family(&mut self) -> Declaration {
+    fn parse_font_family(&mut self) -> Result<Declaration, CssParseError> {
         use super::DeclarationProperty::*;
-        let font = self.consume(&|ch| !matches!(ch, ';'));
-        Declaration::new(FontFamily, DeclarationValue::Other(font))
+        let font = self.consume_font_families()?;
+        Ok(Declaration::new(FontFamily, DeclarationValue::Other(font)))
     }
 
-    fn parse_declaration_margin(&mut self) -> Vec<Declaration> {
+    /// Read comma separated font families. Quoted names keep their quotes, so that the comma in
+    /// a name doesn't split it
+    ///
+    /// e.g. "Times New Roman", 'A, B', serif → "Times New Roman", "A, B", serif
+    fn consume_font_families(&mut self) -> Result<String, CssParseError> {
+        let mut families = vec![];
+        loop {
+            match self.peek() {
+                Some('"' | '\'') => {
+                    let name = self.consume_css_string()?;
+                    families.push(DeclarationValue::String(name).to_string());
+                }
+                Some(_) => {
+                    let name = self.consume(&|ch| !matches!(ch, ',' | ';' | '}'));
+                    families.push(name.trim_end().to_string());
+                }
+                None => break,
+            }
+            match self.peek() {
+                Some(',') => self.bump(),
+                _ => break,
+            }
+        }
+        Ok(families.join(", "))
+    }
+
+    /// e.g.
+    ///   content: "hello, world" → String("hello, world")
+    ///   content: none → Other("none")
+    fn parse_declaration_content(&mut self) -> Result<Declaration, CssParseError> {
+        let value = match self.peek() {
+            Some('"' | '\'') => DeclarationValue::String(self.consume_css_string()?),
+            _ => DeclarationValue::Other(self.consume_raw_value()),
+        };
+        Ok(Declaration::new(DeclarationProperty::Content, value))
+    }
+
+    fn parse_declaration_margin(&mut self) -> Result<Vec<Declaration>, CssParseError> {
         use super::DeclarationProperty::*;
-        let (top, right, bottom, left) = self.parse_declaration_lengths();
-        vec![
+        let (top, right, bottom, left) = self.parse_declaration_lengths()?;
+        Ok(vec![
             Declaration::new(MarginTop, DeclarationValue::Length(top)),
             Declaration::new(MarginRight, DeclarationValue::Length(right)),
             Declaration::new(MarginBottom, DeclarationValue::Length(bottom)),
             Declaration::new(MarginLeft, DeclarationValue::Length(left)),
-        ]
+        ])
     }
 
-    fn parse_declaration_padding(&mut self) -> Vec<Declaration> {
+    fn parse_declaration_padding(&mut self) -> Result<Vec<Declaration>, CssParseError> {
         use super::DeclarationProperty::*;
-        let (top, right, bottom, left) = self.parse_declaration_lengths();
-        vec![
+        let (top, right, bottom, left) = self.parse_declaration_lengths()?;
+        Ok(vec![
             Declaration::new(PaddingTop, DeclarationValue::Length(top)),
             Declaration::new(PaddingRight, DeclarationValue::Length(right)),
             Declaration::new(PaddingBottom, DeclarationValue::Length(bottom)),
             Declaration::new(PaddingLeft, DeclarationValue::Length(left)),
-        ]
+        ])
+    }
+
+    /// Expand `border-radius` shorthand to the radius of each corner. Values are given from
+    /// the top-left corner clockwise, and omitted ones are copied as in `margin`
+    ///
+    /// e.g. 4px 8px → top-left 4px, top-right 8px, bottom-right 4px, bottom-left 8px
+    fn parse_declaration_border_radius(&mut self) -> Result<Vec<Declaration>, CssParseError> {
+        use super::DeclarationProperty::*;
+        let (top_left, top_right, bottom_right, bottom_left) = self.parse_declaration_lengths()?;
+        Ok(vec![
+            Declaration::new(BorderTopLeftRadius, DeclarationValue::Length(top_left)),
+            Declaration::new(BorderTopRightRadius, DeclarationValue::Length(top_right)),
+            Declaration::new(
+                BorderBottomRightRadius,
+                DeclarationValue::Length(bottom_right),
+            ),
+            Declaration::new(
+                BorderBottomLeftRadius,
+                DeclarationValue::Length(bottom_left),
+            ),
+        ])
+    }
+
+    /// Expand `font` shorthand to font-weight, font-size, line-height and font-family.
+    /// font-weight and line-height omitted are reset to their initial values
+    ///
+    /// e.g.
+    ///   bold 16px/1.5 Arial, sans-serif → [FontWeight(700), FontSize(16px), LineHeight(1.5), FontFamily("Arial, sans-serif")]
+    ///   16px Arial → [FontWeight(400), FontSize(16px), LineHeight(normal), FontFamily("Arial")]
+    fn parse_declaration_font(&mut self) -> Result<Vec<Declaration>, CssParseError> {
+        let mut weight = 400;
+        let mut size = None;
+        // font-family when font-size is omitted
+        let mut family = String::new();
+        // font-style, font-variant and font-weight come before font-size in any order
+        while let Some(&ch) = self.peek() {
+            match ch {
+                '0'..='9' | '.' => {
+                    let number = self.consume_number()?;
+                    match self.input.peek() {
+                        Some('%') => {
+                            self.bump();
+                            size = Some(Length::Actual(number, Unit::Pct));
+                            break;
+                        }
+                        Some(ch) if ch.is_ascii_alphabetic() => {
+                            let unit = unit_from_identifier(&self.consume_identifier());
+                            size = Some(Length::Actual(number, unit));
+                            break;
+                        }
+                        _ => weight = number.clamp(1.0, 1000.0) as u16,
+                    }
+                }
+                'a'..='z' | 'A'..='Z' | '-' => match self.consume_identifier().as_str() {
+                    "bold" | "bolder" => weight = 700,
+                    "lighter" => weight = 300,
+                    "normal" | "italic" | "oblique" | "small-caps" => {}
+                    ident => {
+                        family = ident.to_string();
+                        break;
+                    }
+                },
+                _ => break,
+            }
+        }
+
+        let mut declarations = vec![Declaration::new(
+            DeclarationProperty::FontWeight,
+            DeclarationValue::FontWeight(weight),
+        )];
+        if let Some(size) = size {
+            declarations.push(Declaration::new(
+                DeclarationProperty::FontSize,
+                DeclarationValue::Length(size),
+            ));
+        }
+        match self.peek() {
+            Some('/') if family.is_empty() => {
+                self.bump();
+                declarations.push(self.parse_declaration_line_height()?);
+            }
+            _ => declarations.push(Declaration::new(
+                DeclarationProperty::LineHeight,
+                DeclarationValue::LineHeight(LineHeight::Normal),
+            )),
+        }
+        let rest = self.consume_font_families()?;
+        let family = format!("{} {}", family, rest).trim().to_string();
+        if !family.is_empty() {
+            declarations.push(Declaration::new(
+                DeclarationProperty::FontFamily,
+                DeclarationValue::Other(family),
+            ));
+        }
+        Ok(declarations)
     }
 
-    fn parse_declaration_length(&mut self, prop: DeclarationProperty) -> Declaration {
-        let length = match self.peek() {
-            Some('0'..='9') => self.parse_declaration_actual_length(),
-            Some(_) => {
+    fn parse_declaration_length(
+        &mut self,
+        prop: DeclarationProperty,
+    ) -> Result<Declaration, CssParseError> {
+        let length = self.parse_length_or_auto()?;
+        Ok(Declaration::new(prop, DeclarationValue::Length(length)))
+    }
+
+    /// Parse a signed length or a keyword, which is taken as `auto`
+    ///
+    /// e.g.
+    ///   -5px → Actual(-5.0, Px)
+    ///   +.5em → Actual(0.5, Em)
+    ///   auto → Auto
+    fn parse_length_or_auto(&mut self) -> Result<Length, CssParseError> {
+        match self.peek().copied() {
+            Some('0'..='9' | '.') => self.parse_declaration_actual_length(),
+            Some(sign @ ('-' | '+')) if self.is_number_after_sign() => {
+                self.bump();
+                match self.parse_declaration_actual_length()? {
+                    Length::Actual(length, unit) if sign == '-' => {
+                        Ok(Length::Actual(-length, unit))
+                    }
+                    length => Ok(length),
+                }
+            }
+            Some('a'..='z' | 'A'..='Z' | '-') => {
                 let _ = self.consume_identifier();
-                Length::Auto // TODO: Implement other case
+                Ok(Length::Auto) // TODO: Implement other case
             }
-            _ => panic!("Cannot parse declaration lengths"),
-        };
-        Declaration::new(prop, DeclarationValue::Length(length))
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Whether a number follows the sign at the head of input, i.e. -5px but not -webkit-auto
+    fn is_number_after_sign(&self) -> bool {
+        let mut input = self.input.clone();
+        input.next();
+        matches!(input.peek(), Some('0'..='9' | '.'))
     }
 
-    fn parse_declaration_lengths(&mut self) -> (Length, Length, Length, Length) {
+    fn parse_declaration_lengths(
+        &mut self,
+    ) -> Result<(Length, Length, Length, Length), CssParseError> {
         let mut length = vec![];
         let values = loop {
             match self.peek() {
-                Some('0'..='9') => length.push(self.parse_declaration_actual_length()),
-                Some(';') => break length,
-                Some(_) => {
-                    let _ = self.consume_identifier();
-                    length.push(Length::Auto) // TODO: Implement other case
-                }
-                _ => panic!("Cannot parse declaration lengths"),
+                Some(';' | '}') | None => break length,
+                Some(_) => length.push(self.parse_length_or_auto()?),
             }
         };
-        self.skip_next_ch(&';');
+        self.skip_declaration_end()?;
         let values = values.as_slice();
 
         let (top, right, bottom, left) = match values {
@@ -301,9 +869,10 @@ impl<'a> StyleSheetParser<'a> {
             [top, right, bottom, left] => {
                 (top.clone(), right.clone(), bottom.clone(), left.clone())
             }
-            _ => panic!("Cannot parse declaration margin"),
+            // none or more than 4 values
+            _ => return Err(self.unexpected()),
         };
-        (top, right, bottom, left)
+        Ok((top, right, bottom, left))
     }
 
     fn parse_declaration_display(&mut self) -> Declaration {
@@ -322,88 +891,416 @@ impl<'a> StyleSheetParser<'a> {
         )
     }
 
-    fn parse_declaration_actual_length(&mut self) -> Length {
-        let length = self.consume_number();
-        let unit_ident = self.consume_identifier();
+    fn parse_declaration_text_align(&mut self) -> Declaration {
+        Declaration::new(
+            DeclarationProperty::TextAlign,
+            DeclarationValue::TextAlign(TextAlign::from(self.consume_identifier().as_str())),
+        )
+    }
+
+    fn parse_declaration_overflow(&mut self) -> Declaration {
+        Declaration::new(
+            DeclarationProperty::Overflow,
+            DeclarationValue::Overflow(Overflow::from(self.consume_identifier().as_str())),
+        )
+    }
+
+    fn parse_declaration_visibility(&mut self) -> Declaration {
+        Declaration::new(
+            DeclarationProperty::Visibility,
+            DeclarationValue::Visibility(Visibility::from(self.consume_identifier().as_str())),
+        )
+    }
+
+    fn parse_declaration_position(&mut self) -> Declaration {
+        Declaration::new(
+            DeclarationProperty::Position,
+            DeclarationValue::Position(Position::from(self.consume_identifier().as_str())),
+        )
+    }
+
+    fn parse_declaration_flex_direction(&mut self) -> Declaration {
+        let direction = FlexDirection::from(self.consume_identifier().as_str());
+        Declaration::new(
+            DeclarationProperty::FlexDirection,
+            DeclarationValue::FlexDirection(direction),
+        )
+    }
+
+    fn parse_declaration_justify_content(&mut self) -> Declaration {
+        let justify_content = JustifyContent::from(self.consume_identifier().as_str());
+        Declaration::new(
+            DeclarationProperty::JustifyContent,
+            DeclarationValue::JustifyContent(justify_content),
+        )
+    }
+
+    /// Parse `grid-template-columns`
+    ///
+    /// e.g.
+    ///   100px 1fr 2fr → [Length(100px), Fr(1), Fr(2)]
+    ///   repeat(2, 1fr) → [Fr(1), Fr(1)]
+    fn parse_declaration_grid_template_columns(&mut self) -> Result<Declaration, CssParseError> {
+        Ok(Declaration::new(
+            DeclarationProperty::GridTemplateColumns,
+            DeclarationValue::TrackList(self.parse_track_sizes()?),
+        ))
+    }
+
+    /// Parse track sizes up to `)` or the end. Keywords such as `auto` are auto sized
+    fn parse_track_sizes(&mut self) -> Result<Vec<TrackSize>, CssParseError> {
+        let mut tracks = vec![];
+        while let Some(&ch) = self.peek() {
+            match ch {
+                ')' => break,
+                '0'..='9' | '.' => {
+                    let number = self.consume_number()?;
+                    let track = match self.input.peek() {
+                        Some('%') => {
+                            self.bump();
+                            TrackSize::Length(Length::Actual(number, Unit::Pct))
+                        }
+                        _ => match self.consume_identifier().as_str() {
+                            "fr" => TrackSize::Fr(number),
+                            unit => TrackSize::Length(Length::Actual(
+                                number,
+                                unit_from_identifier(unit),
+                            )),
+                        },
+                    };
+                    tracks.push(track);
+                }
+                _ => match self.consume_identifier().as_str() {
+                    "repeat" if self.input.next_if_eq(&'(').is_some() => {
+                        let count = self.consume_number()? as usize;
+                        if let Some(',') = self.peek() {
+                            self.bump();
+                        }
+                        let repeated = self.parse_track_sizes()?;
+                        if let Some(')') = self.peek() {
+                            self.bump();
+                        }
+                        for _ in 0..count {
+                            tracks.extend(repeated.iter().cloned());
+                        }
+                    }
+                    // skip unsupported syntax, e.g. [line-name]
+                    "" => self.bump(),
+                    _ => tracks.push(TrackSize::Length(Length::Auto)),
+                },
+            }
+        }
+        Ok(tracks)
+    }
+
+    /// Parse `flex` to its flex-grow. Shrink and basis are not used by layout, so skipped
+    ///
+    /// e.g.
+    ///   2 1 0% → 2
+    ///   auto → 1
+    ///   none → 0
+    fn parse_declaration_flex(&mut self) -> Result<Declaration, CssParseError> {
+        let grow = match self.peek() {
+            Some('0'..='9' | '.') => self.consume_number()?,
+            _ => match self.consume_identifier().as_str() {
+                "auto" => 1.0,
+                // `none` and the initial value `0 1 auto`
+                _ => 0.0,
+            },
+        };
+        self.consume_raw_value();
+        Ok(Declaration::new(
+            DeclarationProperty::Flex,
+            DeclarationValue::Number(grow),
+        ))
+    }
+
+    /// Parse `opacity` to a number clamped to 0..=1. Percentage is divided by 100
+    ///
+    /// e.g.
+    ///   0.5 → 0.5
+    ///   50% → 0.5
+    ///   -1 → 0
+    ///   inherit → 1 (the initial value)
+    fn parse_declaration_opacity(&mut self) -> Result<Declaration, CssParseError> {
+        let negative = self.peek() == Some(&'-');
+        if negative {
+            self.bump();
+        }
+        let opacity = match self.peek() {
+            Some('0'..='9' | '.') => {
+                let number = self.consume_number()?;
+                let number = if self.input.next_if_eq(&'%').is_some() {
+                    number / 100.0
+                } else {
+                    number
+                };
+                if negative {
+                    -number
+                } else {
+                    number
+                }
+            }
+            _ => {
+                self.consume_identifier();
+                1.0
+            }
+        };
+        Ok(Declaration::new(
+            DeclarationProperty::Opacity,
+            DeclarationValue::Number(opacity.clamp(0.0, 1.0)),
+        ))
+    }
+
+    /// Parse `font-weight` to numeric weight
+    ///
+    /// e.g.
+    ///   bold → 700
+    ///   600 → 600
+    fn parse_declaration_font_weight(&mut self) -> Result<Declaration, CssParseError> {
+        let weight = match self.peek() {
+            Some('0'..='9') => self.consume_number()?.clamp(1.0, 1000.0) as u16,
+            _ => match self.consume_identifier().as_str() {
+                "bold" | "bolder" => 700,
+                "lighter" => 300,
+                _ => 400,
+            },
+        };
+        Ok(Declaration::new(
+            DeclarationProperty::FontWeight,
+            DeclarationValue::FontWeight(weight),
+        ))
+    }
+
+    /// Parse `line-height`, which is a length or a unitless multiplier of the font size
+    ///
+    /// e.g.
+    ///   24px → LineHeight::Length(Length::Actual(24.0, Unit::Px))
+    ///   1.5 → LineHeight::Number(1.5)
+    fn parse_declaration_line_height(&mut self) -> Result<Declaration, CssParseError> {
+        let line_height = match self.peek() {
+            Some('0'..='9' | '.') => {
+                let number = self.consume_number()?;
+                match self.input.peek() {
+                    Some('%') => {
+                        self.bump();
+                        LineHeight::Length(Length::Actual(number, Unit::Pct))
+                    }
+                    Some(ch) if ch.is_ascii_alphabetic() => {
+                        let unit = unit_from_identifier(&self.consume_identifier());
+                        LineHeight::Length(Length::Actual(number, unit))
+                    }
+                    _ => LineHeight::Number(number),
+                }
+            }
+            _ => {
+                let _ = self.consume_identifier();
+                LineHeight::Normal
+            }
+        };
+        Ok(Declaration::new(
+            DeclarationProperty::LineHeight,
+            DeclarationValue::LineHeight(line_height),
+        ))
+    }
+
+    /// Parse number with unit, e.g. 10px, 1.5em, 50%
+    ///
+    /// Unit can be omitted only for zero, i.e. `0` → Length::Actual(0.0, Unit::Px)
+    fn parse_declaration_actual_length(&mut self) -> Result<Length, CssParseError> {
+        let length = self.consume_number()?;
+        if let Some('%') = self.input.peek() {
+            self.bump();
+            return Ok(Length::Actual(length, Unit::Pct));
+        }
+        // unit follows the number without spaces, e.g. `auto` of `0 auto` is not a unit
+        let mut unit_ident = String::new();
+        while let Some(ch) = self.input.next_if(char::is_ascii_alphabetic) {
+            unit_ident.push(ch);
+        }
         let unit = match unit_ident.as_str() {
-            "px" => Unit::Px,
-            "em" => Unit::Em,
-            _ => Unit::Px,
+            "" if length == 0.0 => Unit::Px,
+            "" => return Err(CssParseError::ExpectedIdentifier(self.position())),
+            unit => unit_from_identifier(unit),
         };
-        Length::Actual(length, unit)
+        Ok(Length::Actual(length, unit))
     }
 
-    fn parse_declaration_color(&mut self, property: DeclarationProperty) -> Declaration {
-        self.next();
-        let r = self.consume_hex(2);
-        let g = self.consume_hex(2);
-        let b = self.consume_hex(2);
-        let a = match self.input.peek() {
-            Some(ch) if matches!(ch, '0'..='9' | 'a'..='z' | 'A'..='Z') => self.consume_hex(2),
-            _ => 0,
+    /// Parse color value of `color`, `background-color`
+    ///
+    /// e.g.
+    ///   #cc0000, rgb(204, 0, 0), rgba(0, 0, 0, 0.5), red, transparent
+    ///
+    /// Other keywords are errors, including `inherit` and `currentColor` which are not supported,
+    /// so that the declaration is skipped
+    fn parse_declaration_color(
+        &mut self,
+        property: DeclarationProperty,
+    ) -> Result<Declaration, CssParseError> {
+        let color = match self.peek().copied() {
+            Some('#') => self.parse_hex_color()?,
+            Some(ch @ ('a'..='z' | 'A'..='Z')) => {
+                let start = self.input.clone();
+                let ident = self.consume_identifier();
+                match ident.to_lowercase().as_str() {
+                    "rgb" | "rgba" => self.parse_rgba_arguments()?,
+                    "transparent" => Color::new(0x00, 0x00, 0x00, 0x00),
+                    name => Color::from_name(name).ok_or_else(|| {
+                        CssParseError::UnexpectedChar(ch, self.position_at(&start))
+                    })?,
+                }
+            }
+            _ => return Err(self.unexpected()),
         };
-        Declaration::new(property, DeclarationValue::Color(Color::new(r, g, b, a)))
-    }
-
-    fn parse_rgba(&mut self) -> Color {
-        self.skip_next_str("rgba(");
-        let r = self.consume_number() as usize;
-        self.skip_next_ch(&',');
-        let g = self.consume_number() as usize;
-        self.skip_next_ch(&',');
-        let b = self.consume_number() as usize;
-        let a = match self.input.peek() {
-            Some(ch) if matches!(ch, ',') => {
-                self.skip_next_ch(&',');
-                self.consume_number() as usize
-            }
-            _ => 0_usize,
+        Ok(Declaration::new(property, DeclarationValue::Color(color)))
+    }
+
+    fn parse_hex_color(&mut self) -> Result<Color, CssParseError> {
+        let start = self.input.clone();
+        self.next();
+        let hex = self.consume(&|ch| ch.is_ascii_hexdigit());
+        // e.g. `#` alone, or `#12` of neither 3, 4, 6 nor 8 digits
+        Color::from_hex(&hex)
+            .ok_or_else(|| CssParseError::UnexpectedChar('#', self.position_at(&start)))
+    }
+
+    fn parse_rgba(&mut self) -> Result<Color, CssParseError> {
+        self.skip_next_str("rgba")?;
+        self.parse_rgba_arguments()
+    }
+
+    /// Parse arguments of rgb()/rgba(), i.e. `(r, g, b[, a])` or `(r g b[ / a])`
+    ///
+    /// Channels are numbers in 0..=255 or percentages. alpha is given in 0.0..=1.0 or
+    /// a percentage, and stored in 0..=255
+    ///
+    /// e.g.
+    ///   (255, 0, 0, 0.5)   → Color::new(255, 0, 0, 128)
+    ///   (100% 0% 0% / 50%) → Color::new(255, 0, 0, 128)
+    fn parse_rgba_arguments(&mut self) -> Result<Color, CssParseError> {
+        self.skip_next_str("(")?;
+        let r = self.parse_rgb_channel()?;
+        self.skip_rgb_separator();
+        let g = self.parse_rgb_channel()?;
+        self.skip_rgb_separator();
+        let b = self.parse_rgb_channel()?;
+        let a = match self.peek() {
+            Some(',' | '/') => {
+                self.bump();
+                (self.parse_percentage_or_number(1.0)?.clamp(0.0, 1.0) * 255.0).round() as usize
+            }
+            _ => 0xff,
         };
-        self.skip_next_ch(&')');
-        Color::new(r, g, b, a)
+        self.skip_next_ch(&')')?;
+        Ok(Color::new(r, g, b, a))
     }
 
-    fn parse_declaration_other(&mut self, s: String) -> Declaration {
-        let ident = self.consume_identifier();
-        Declaration::new(
-            DeclarationProperty::Other(s),
-            DeclarationValue::Other(ident),
-        )
+    fn parse_rgb_channel(&mut self) -> Result<usize, CssParseError> {
+        let channel = self.parse_percentage_or_number(255.0)?;
+        Ok(channel.clamp(0.0, 255.0).round() as usize)
     }
 
-    fn consume_identifier(&mut self) -> String {
-        self.consume(&|ch| matches!(ch, '0'..='9' | 'a'..='z' | 'A'..='Z' | '_' | '-'))
+    /// Number, or percentage of `max`, e.g. 50% of 255 → 127.5
+    fn parse_percentage_or_number(&mut self, max: f32) -> Result<f32, CssParseError> {
+        let number = self.consume_number()?;
+        let number = if self.input.next_if_eq(&'%').is_some() {
+            number * max / 100.0
+        } else {
+            number
+        };
+        self.skip_whitespace();
+        Ok(number)
     }
 
-    fn consume_number(&mut self) -> f32 {
-        self.consume(&|ch| matches!(ch, '0'..='9' | '.'))
-            .parse()
-            .unwrap()
+    /// Channels are separated by comma or whitespace only
+    fn skip_rgb_separator(&mut self) {
+        if let Some(',') = self.peek() {
+            self.bump();
+        }
     }
 
-    fn consume_hex(&mut self, n: usize) -> usize {
-        usize::from_str_radix(
-            &self.consume_for(&|ch| matches!(ch, '0'..='9' | 'a'..='f' | 'A'..='F'), n),
-            16,
-        )
-        .unwrap_or_default()
+    /// Keep the whole value of unsupported or opaque property as is, so that it round-trips
+    ///
+    /// e.g. -webkit-transform: translate(10px, 20px) → Other("translate(10px, 20px)")
+    ///      transition: all 0.2s ease-in-out          → Other("all 0.2s ease-in-out")
+    fn parse_declaration_raw(&mut self, property: DeclarationProperty) -> Declaration {
+        let value = self.consume_raw_value();
+        Declaration::new(property, DeclarationValue::Other(value))
     }
 
-    /// Get until n-th character strings according to consume_condition
-    fn consume_for<F>(&mut self, consume_condition: &F, nth: usize) -> String
-    where
-        F: Fn(&char) -> bool,
-    {
+    /// Read the declaration value up to `;` or `}`. They don't end the value inside
+    /// parentheses or quotes, e.g. url("data:image/png;base64,...") or content: "}"
+    fn consume_raw_value(&mut self) -> String {
         self.skip_whitespace();
+        let mut value = String::new();
+        let mut depth = 0;
+        let mut quote = None;
+        while let Some(&ch) = self.input.peek() {
+            match (quote, ch) {
+                (Some(_), '\\') => {
+                    // escaped character is kept as written, e.g. \" of "say \"hi\""
+                    value.push(ch);
+                    self.input.next();
+                    if let Some(escaped) = self.input.next() {
+                        value.push(escaped);
+                    }
+                    continue;
+                }
+                (Some(q), _) if ch == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(ch),
+                (None, '(') => depth += 1,
+                (None, ')') if depth > 0 => depth -= 1,
+                (None, ';' | '}') if depth == 0 => break,
+                _ => {}
+            }
+            value.push(ch);
+            self.input.next();
+        }
+        value.trim_end().to_string()
+    }
+
+    /// Read a quoted string without its quotes. Backslash escapes the next character
+    ///
+    /// e.g. "hello, world" → hello, world, 'it\'s' → it's
+    fn consume_css_string(&mut self) -> Result<String, CssParseError> {
+        let quote = match self.peek() {
+            Some(&ch) if matches!(ch, '"' | '\'') => ch,
+            _ => return Err(self.unexpected()),
+        };
+        self.bump();
         let mut s = String::new();
-        for _ in 0..nth {
-            match self.input.next_if(consume_condition) {
-                Some(ch) => s.push_str(&ch.to_string()),
-                _ => break,
+        loop {
+            match self.input.next() {
+                Some(ch) if ch == quote => return Ok(s),
+                Some('\\') => match self.input.next() {
+                    Some(escaped) => s.push(escaped),
+                    None => return Err(CssParseError::UnexpectedEof),
+                },
+                Some(ch) => s.push(ch),
+                None => return Err(CssParseError::UnexpectedEof),
             }
         }
+    }
+
+    fn consume_identifier(&mut self) -> String {
+        self.consume(&|ch| matches!(ch, '0'..='9' | 'a'..='z' | 'A'..='Z' | '_' | '-'))
+    }
+
+    /// Consume number including a leading or trailing decimal point, e.g. .5, 0.5, 5.
+    ///
+    /// Whitespace after the number is kept, so that callers can tell `1.5em` from `1.5 Arial`
+    fn consume_number(&mut self) -> Result<f32, CssParseError> {
         self.skip_whitespace();
-        s
+        let start = self.input.clone();
+        let mut number = String::new();
+        while let Some(ch) = self.input.next_if(|ch| matches!(ch, '0'..='9' | '.')) {
+            number.push(ch);
+        }
+        number.parse().map_err(|_| match number.chars().next() {
+            Some(ch) => CssParseError::UnexpectedChar(ch, self.position_at(&start)),
+            None => self.unexpected(),
+        })
     }
 
     /// Get strings according to consume_condition
@@ -421,11 +1318,53 @@ impl<'a> StyleSheetParser<'a> {
     }
 
     /// Skip specific next character
-    fn skip_next_ch(&mut self, ch: &char) {
-        match self.input.next() {
-            Some(ref c) if c == ch => {}
-            _ => panic!("Cannot found {}", ch),
-        };
+    fn skip_next_ch(&mut self, ch: &char) -> Result<(), CssParseError> {
+        match self.input.peek() {
+            Some(c) if c == ch => {
+                self.input.next();
+                Ok(())
+            }
+            Some(&c) => Err(CssParseError::UnexpectedChar(c, self.position())),
+            None => Err(CssParseError::UnexpectedEof),
+        }
+    }
+
+    /// Skip `;` or the balanced `{ ... }` block which ends an unsupported at-rule
+    fn skip_at_rule_body(&mut self) {
+        if let Some('{') = self.input.next() {
+            let mut depth = 1;
+            while depth > 0 {
+                match self.input.next() {
+                    Some('{') => depth += 1,
+                    Some('}') => depth -= 1,
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+        }
+    }
+
+    /// Skip `;` of the end of declaration, it can be omitted before `}` or EOF
+    fn skip_declaration_end(&mut self) -> Result<(), CssParseError> {
+        match self.peek() {
+            Some(';') => self.bump(),
+            Some('}') | None => {}
+            Some(_) => return Err(self.unexpected()),
+        }
+        Ok(())
+    }
+
+    /// Skip the rest of the rule whose selector has an error, i.e. to the `}` which closes its block
+    fn skip_rule_rest(&mut self) {
+        let mut depth = 0;
+        for ch in self.input.by_ref() {
+            match ch {
+                '{' => depth += 1,
+                '}' if depth <= 1 => break,
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
     }
 
     fn skip_whitespace(&mut self) {
@@ -449,41 +1388,364 @@ impl<'a> StyleSheetParser<'a> {
         self.input.peek()
     }
 
-    fn skip_next_str(&mut self, s: &'static str) {
+    fn skip_next_str(&mut self, s: &'static str) -> Result<(), CssParseError> {
         self.skip_whitespace();
         for ch in s.chars() {
-            match self.input.next() {
-                Some(c) if c == ch => {}
-                _ => panic!("Cannot found {}", ch),
-            };
+            self.skip_next_ch(&ch)?;
         }
+        Ok(())
     }
 }
 
+/// Unit of length, px for unknown ones
+///
+/// e.g. em → Unit::Em, pt → Unit::Pt
+fn unit_from_identifier(unit: &str) -> Unit {
+    match unit.to_ascii_lowercase().as_str() {
+        "em" => Unit::Em,
+        "ex" => Unit::Ex,
+        "ch" => Unit::Ch,
+        "rem" => Unit::Rem,
+        "vh" => Unit::Vh,
+        "vw" => Unit::Vw,
+        "vmin" => Unit::Vmin,
+        "vmax" => Unit::Vmax,
+        "mm" => Unit::Mm,
+        "q" => Unit::Q,
+        "cm" => Unit::Cm,
+        "in" => Unit::In,
+        "pt" => Unit::Pt,
+        "pc" => Unit::Pc,
+        _ => Unit::Px,
+    }
+}
+
+/// Split `!important` off the end of declaration value
+///
+/// e.g.
+///   red !important → ("red", true)
+///   10px → ("10px", false)
+fn split_important(value: &str) -> (&str, bool) {
+    if let Some((rest, flag)) = value.rsplit_once('!') {
+        if flag.trim().eq_ignore_ascii_case("important") {
+            return (rest.trim_end(), true);
+        }
+    }
+    (value, false)
+}
+
 impl StyleSheet {
-    pub fn new(rules: Vec<Rule>, media_query: Option<String>) -> Self {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        let index = RuleIndex::new(&rules);
         Self {
             rules,
-            media_query,
+            index,
+            charset: None,
         }
     }
 
-    /// TODO: ??????
-    pub fn get_styles(&self, element: &Element) -> StyleMap {
+    /// Encoding declared by `@charset` at the top of the stylesheet. It is informational,
+    /// since the input is already decoded as UTF-8
+    ///
+    /// e.g. `@charset "utf-8";` → Some("utf-8")
+    pub fn charset(&self) -> Option<String> {
+        self.charset.clone()
+    }
+
+    /// Rules in source order
+    ///
+    /// ```
+    /// use crate::lib::StyleSheetParser;
+    /// let stylesheet = StyleSheetParser::new("h1, p { color: red } p { margin: 0 }").parse().unwrap();
+    /// let counts = stylesheet
+    ///     .rules()
+    ///     .iter()
+    ///     .map(|rule| (rule.selectors().len(), rule.declarations().len()))
+    ///     .collect::<Vec<_>>();
+    /// // margin is expanded to margin-top, margin-right, margin-bottom and margin-left
+    /// assert_eq!(counts, vec![(2, 1), (1, 4)]);
+    /// ```
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Rules which have a selector matching the element, in source order, e.g. to find out why a
+    /// style is (not) applied. Rules in `@media` are included regardless of the media.
+    /// The element is matched alone, so selectors which need the parent or siblings don't match
+    ///
+    /// e.g. for <p class="note">, `p { color: red }` and `.note { color: blue }` but not `div {}`
+    pub fn rules_matching(&self, element: &Element) -> Vec<&Rule> {
+        let context = MatchContext::new(element);
+        self.index
+            .candidates(element)
+            .into_iter()
+            .map(|i| &self.rules[i])
+            .filter(|rule| {
+                rule.selectors
+                    .iter()
+                    .any(|selector| selector.matches_in(&context))
+            })
+            .collect()
+    }
+
+    /// Append rules of other after the ones of self, so that other wins ties as a later sheet
+    ///
+    /// e.g. user_agent_stylesheet().merge(author) cascades the author's rules over the UA's
+    pub fn merge(&mut self, other: StyleSheet) {
+        self.rules.extend(other.rules);
+        self.index = RuleIndex::new(&self.rules);
+    }
+
+    /// Collect normal (i.e. not `!important`) declarations of rules which match the element.
+    /// Later rules override earlier ones. Rules in `@media` are skipped, see `get_styles_for_viewport`
+    pub fn get_styles(&self, context: &MatchContext) -> StyleMap {
+        self.collect_styles(context, None, false)
+    }
+
+    /// Same as `get_styles`, but collect `!important` declarations only
+    pub fn get_important_styles(&self, context: &MatchContext) -> StyleMap {
+        self.collect_styles(context, None, true)
+    }
+
+    /// Same as `get_styles`, but also applies rules in `@media` which matches the viewport width
+    pub fn get_styles_for_viewport(&self, context: &MatchContext, viewport_width: f64) -> StyleMap {
+        self.collect_styles(context, Some(viewport_width), false)
+    }
+
+    fn collect_styles(
+        &self,
+        context: &MatchContext,
+        viewport_width: Option<f64>,
+        important: bool,
+    ) -> StyleMap {
         let mut styles = StyleMap::new();
 
-        for rule in self.rules.iter() {
-            for selector in rule.selectors.iter() {
-                if selector.matches(element) {
-                    for declaration in rule.declarations.iter() {
-                        styles.insert(declaration.property.clone(), declaration.value.clone());
-                    }
-                    break;
-                }
+        for i in self.index.candidates(context.element) {
+            let rule = &self.rules[i];
+            let in_viewport = match (&rule.media, viewport_width) {
+                (None, _) => true,
+                (Some(media), Some(width)) => media.matches(width),
+                (Some(_), None) => false,
+            };
+            if in_viewport
+                && rule
+                    .selectors
+                    .iter()
+                    .any(|selector| selector.matches_in(context))
+            {
+                styles.extend(rule.get_styles(important));
+            }
+        }
+        styles
+    }
+
+    /// Whether a rule which possibly matches the element has combinators,
+    /// i.e. the result depends on the parent and siblings of the element
+    fn is_contextual_for(&self, element: &Element) -> bool {
+        self.index
+            .candidates(element)
+            .into_iter()
+            .any(|i| self.rules[i].selectors.iter().any(Selector::is_contextual))
+    }
+}
+
+impl StyleEngine {
+    /// StyleEngine which caches StyleMap per element signature
+    pub fn new(stylesheet: StyleSheet) -> Self {
+        Self {
+            stylesheet,
+            user_agent: user_agent_stylesheet(),
+            cache: Some(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// StyleEngine which computes StyleMap from scratch every time
+    pub fn without_cache(stylesheet: StyleSheet) -> Self {
+        Self {
+            stylesheet,
+            user_agent: user_agent_stylesheet(),
+            cache: None,
+        }
+    }
+
+    pub fn stylesheet(&self) -> &StyleSheet {
+        &self.stylesheet
+    }
+
+    /// Cascaded styles of the element. Later ones override earlier ones
+    ///   1. normal declarations of the user agent
+    ///   2. normal declarations of the author's stylesheet
+    ///   3. normal declarations of inline style, i.e. `style` attribute of the element
+    ///   4. `!important` declarations of the user agent and the author's stylesheet
+    ///   5. `!important` declarations of inline style
+    ///
+    /// e.g. `p { color: red !important }` wins over `<p style="color: blue">`,
+    /// but loses to `<p style="color: blue !important">`
+    pub fn get_styles(&self, context: &MatchContext) -> StyleMap {
+        let (mut styles, important) = self.get_stylesheet_styles(context);
+        let (inline_important, inline): (Vec<_>, Vec<_>) = context
+            .element
+            .get_inline_style()
+            .map(|style| StyleSheetParser::new(style).parse_inline())
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|declaration| declaration.important);
+
+        for declaration in inline {
+            styles.insert(declaration.property, declaration.value);
+        }
+        styles.extend(important);
+        for declaration in inline_important {
+            styles.insert(declaration.property, declaration.value);
+        }
+        styles
+    }
+
+    /// Normal and `!important` styles from the stylesheets. Elements which have the same signature
+    /// share the result
+    ///
+    /// Elements which combinators may match are not cached, since their styles depend on the context
+    fn get_stylesheet_styles(&self, context: &MatchContext) -> (StyleMap, StyleMap) {
+        let element = context.element;
+        let cache = match &self.cache {
+            Some(cache)
+                if !self.stylesheet.is_contextual_for(element)
+                    && !self.user_agent.is_contextual_for(element) =>
+            {
+                cache
             }
+            _ => return self.compute_styles(context),
+        };
+        let signature = ElementSignature::new(element);
+        if let Some(styles) = cache.borrow().get(&signature) {
+            return styles.clone();
         }
+        let styles = self.compute_styles(context);
+        cache.borrow_mut().insert(signature, styles.clone());
         styles
     }
+
+    /// User agent styles overridden by the author's stylesheet, for normal and `!important` ones
+    fn compute_styles(&self, context: &MatchContext) -> (StyleMap, StyleMap) {
+        let mut styles = self.user_agent.get_styles(context);
+        styles.extend(self.stylesheet.get_styles(context));
+        let mut important = self.user_agent.get_important_styles(context);
+        important.extend(self.stylesheet.get_important_styles(context));
+        (styles, important)
+    }
+}
+
+impl<'a> MatchContext<'a> {
+    /// Context of the element without parent and siblings, where combinators never match
+    pub fn new(element: &'a Element) -> Self {
+        Self {
+            element,
+            parent: None,
+            siblings: &[],
+            index: 0,
+        }
+    }
+
+    /// Ancestors of the element, the parent first
+    fn ancestors(&self) -> impl Iterator<Item = &'a MatchContext<'a>> {
+        std::iter::successors(self.parent, |context| context.parent)
+    }
+
+    /// Preceding sibling elements, the nearest first
+    fn preceding_siblings(&self) -> impl Iterator<Item = MatchContext<'a>> {
+        let parent = self.parent;
+        let siblings = self.siblings;
+        siblings
+            .iter()
+            .enumerate()
+            .take(self.index)
+            .rev()
+            .filter_map(move |(index, node)| match node {
+                Node::Element(element) => Some(MatchContext {
+                    element,
+                    parent,
+                    siblings,
+                    index,
+                }),
+                _ => None,
+            })
+    }
+
+    /// Whether any sibling element follows the element
+    fn has_following_sibling(&self) -> bool {
+        self.siblings
+            .iter()
+            .skip(self.index + 1)
+            .any(|node| matches!(node, Node::Element(_)))
+    }
+}
+
+impl ElementSignature {
+    fn new(element: &Element) -> Self {
+        let mut classes = element
+            .get_classes()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|class_name| class_name.to_string())
+            .collect::<Vec<_>>();
+        classes.sort_unstable();
+        classes.dedup();
+        Self {
+            tag_name: element.tag_name.clone(),
+            classes,
+            id: element.get_id().map(|id| id.to_string()),
+        }
+    }
+}
+
+impl RuleIndex {
+    pub(crate) fn new(rules: &[Rule]) -> Self {
+        let mut index = Self::default();
+        for (i, rule) in rules.iter().enumerate() {
+            for selector in rule.selectors.iter() {
+                index.insert(selector, i);
+            }
+        }
+        index
+    }
+
+    fn insert(&mut self, selector: &Selector, i: usize) {
+        let rules = match selector {
+            Selector::Tag(tag_name) => self.by_tag.entry(tag_name.clone()).or_default(),
+            Selector::Class(_, class_name) => self.by_class.entry(class_name.clone()).or_default(),
+            Selector::Id(_, id) => self.by_id.entry(id.clone()).or_default(),
+            // the element must match the right selector
+            Selector::Child(_, box right)
+            | Selector::Adjacent(_, box right)
+            | Selector::GeneralSibling(_, box right)
+            | Selector::Descendant(_, box right) => return self.insert(right, i),
+            Selector::Pseudo(Some(box selector), _) => return self.insert(selector, i),
+            Selector::Pseudo(None, _) => &mut self.universal,
+        };
+        // a rule which has several selectors of the same key is stored once
+        if rules.last() != Some(&i) {
+            rules.push(i);
+        }
+    }
+
+    /// Positions of rules which possibly match the element, in source order
+    fn candidates(&self, element: &Element) -> Vec<usize> {
+        let mut candidates = self.universal.clone();
+        if let Some(rules) = self.by_tag.get(&element.tag_name) {
+            candidates.extend(rules);
+        }
+        if let Some(rules) = self.by_id.get(element.get_id().unwrap_or_default()) {
+            candidates.extend(rules);
+        }
+        for class_name in element.get_classes().unwrap_or_default().split_whitespace() {
+            if let Some(rules) = self.by_class.get(class_name) {
+                candidates.extend(rules);
+            }
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
 }
 
 impl Rule {
@@ -491,11 +1753,116 @@ impl Rule {
         Self {
             selectors,
             declarations,
+            media: None,
+        }
+    }
+
+    pub fn selectors(&self) -> &[Selector] {
+        &self.selectors
+    }
+
+    pub fn declarations(&self) -> &[Declaration] {
+        &self.declarations
+    }
+
+    /// Values of either normal or `!important` declarations of the rule by property.
+    /// When a property is declared twice, the later one wins
+    ///
+    /// e.g. `color: red; color: blue` → { color: blue }. `!important` ones are collected
+    /// separately, so `color: red !important; color: blue` still cascades red
+    pub fn get_styles(&self, important: bool) -> StyleMap {
+        let mut styles = StyleMap::new();
+        for declaration in self.declarations.iter() {
+            if declaration.important == important {
+                // overrides the earlier declaration of the same property
+                styles.insert(declaration.property.clone(), declaration.value.clone());
+            }
+        }
+        styles
+    }
+}
+
+impl MediaQuery {
+    /// Whether any group of conditions matches the viewport width. The page is rendered on
+    /// screen, so `screen` and `all` match but other media types (e.g. `print`) don't
+    pub fn matches(&self, viewport_width: f64) -> bool {
+        self.queries
+            .iter()
+            .any(|conditions| conditions.iter().all(|c| c.matches(viewport_width)))
+    }
+}
+
+impl MediaCondition {
+    /// em and rem in media features are relative to the initial font size (16px)
+    fn matches(&self, viewport_width: f64) -> bool {
+        let to_px = |length: &Length| length.to_px(16.0, 16.0, viewport_width);
+        match self {
+            MediaCondition::MediaType(media_type) => {
+                matches!(media_type.as_str(), "screen" | "all")
+            }
+            MediaCondition::MinWidth(length) => viewport_width >= to_px(length),
+            MediaCondition::MaxWidth(length) => viewport_width <= to_px(length),
+            MediaCondition::Not(conditions) => {
+                !conditions.iter().all(|c| c.matches(viewport_width))
+            }
+            MediaCondition::Other(_) => false,
         }
     }
 }
 
 impl Selector {
+    /// Parse one selector (which may have combinators) from input
+    ///
+    /// e.g.
+    ///   div.note     → Ok(Selector::Class(Some(box (Selector::Tag(Div))), "note".to_string()))
+    ///   article > p  → Ok(Selector::Child(box (Selector::Tag(Article)), box (Selector::Tag(P))))
+    ///   div..        → Err(CssParseError::ExpectedIdentifier(4))
+    pub fn parse(input: &str) -> Result<Selector, CssParseError> {
+        let mut parser = StyleSheetParser::new(input);
+        let selector = parser.parse_one_selector()?;
+        match parser.peek() {
+            Some(_) => Err(parser.unexpected()),
+            None => Ok(selector),
+        }
+    }
+
+    /// Specificity of selector, i.e. (the number of ids, classes and pseudo-classes, tags)
+    ///
+    /// e.g.
+    ///   div#note.box → (1, 1, 1)
+    ///   article > p  → (0, 0, 2)
+    pub fn specificity(&self) -> (usize, usize, usize) {
+        let left_specificity = |left: &Option<Box<Selector>>| match left {
+            Some(box selector) => selector.specificity(),
+            None => (0, 0, 0),
+        };
+        match self {
+            Selector::Tag(_) => (0, 0, 1),
+            // :not counts as its argument
+            Selector::Pseudo(left, PseudoClass::Not(box selector)) => {
+                let (a, b, c) = left_specificity(left);
+                let (d, e, f) = selector.specificity();
+                (a + d, b + e, c + f)
+            }
+            Selector::Class(left, _) | Selector::Pseudo(left, _) => {
+                let (a, b, c) = left_specificity(left);
+                (a, b + 1, c)
+            }
+            Selector::Id(left, _) => {
+                let (a, b, c) = left_specificity(left);
+                (a + 1, b, c)
+            }
+            Selector::Child(box left, box right)
+            | Selector::Adjacent(box left, box right)
+            | Selector::GeneralSibling(box left, box right)
+            | Selector::Descendant(box left, box right) => {
+                let (a, b, c) = left.specificity();
+                let (d, e, f) = right.specificity();
+                (a + d, b + e, c + f)
+            }
+        }
+    }
+
     /// Elementオブジェクト(e.g. <div id="book" />)を渡されたとき、それに該当するCSS Selectorかどうか判断する
     ///
     /// e.g. it returns true when selector is div#book and element is <div id="book">.
@@ -517,13 +1884,9 @@ impl Selector {
         match &self {
             Selector::Tag(tag_name) => tag_name == &element.tag_name,
             Selector::Class(Some(box selector), class_name) => {
-                let element_class_name = &element.get_classes().unwrap_or_default();
-                selector.matches(element) && class_name == element_class_name
-            }
-            Selector::Class(None, class_name) => {
-                let element_class_name = &element.get_classes().unwrap_or_default();
-                class_name == element_class_name
+                selector.matches(element) && element.has_class(class_name)
             }
+            Selector::Class(None, class_name) => element.has_class(class_name),
             Selector::Id(Some(box selector), id) => {
                 let element_id = &element.get_id().unwrap_or_default();
                 selector.matches(element) && id == element_id
@@ -532,6 +1895,87 @@ impl Selector {
                 let element_id = &element.get_id().unwrap_or_default();
                 id == element_id
             }
+            Selector::Pseudo(Some(box left), PseudoClass::Not(box selector)) => {
+                left.matches(element) && !selector.matches(element)
+            }
+            Selector::Pseudo(None, PseudoClass::Not(box selector)) => !selector.matches(element),
+            _ => false,
+        }
+    }
+
+    /// Same as `matches`, and combinators are checked against the parent and siblings in the context
+    ///
+    /// e.g. h1 ~ p matches <p> when any sibling element before it is <h1>
+    pub fn matches_in(&self, context: &MatchContext) -> bool {
+        match self {
+            Selector::Child(box left, box right) => {
+                right.matches_in(context)
+                    && context.parent.is_some_and(|parent| left.matches_in(parent))
+            }
+            Selector::Descendant(box left, box right) => {
+                right.matches_in(context)
+                    && context
+                        .ancestors()
+                        .any(|ancestor| left.matches_in(ancestor))
+            }
+            Selector::Adjacent(box left, box right) => {
+                right.matches_in(context)
+                    && context
+                        .preceding_siblings()
+                        .next()
+                        .is_some_and(|sibling| left.matches_in(&sibling))
+            }
+            Selector::GeneralSibling(box left, box right) => {
+                right.matches_in(context)
+                    && context
+                        .preceding_siblings()
+                        .any(|sibling| left.matches_in(&sibling))
+            }
+            Selector::Class(Some(box left), class_name) => {
+                left.matches_in(context) && context.element.has_class(class_name)
+            }
+            Selector::Id(Some(box left), id) => {
+                left.matches_in(context) && context.element.get_id() == Some(id.as_str())
+            }
+            Selector::Pseudo(left, pseudo_class) => {
+                let left = match left {
+                    Some(box left) => left.matches_in(context),
+                    None => true,
+                };
+                left && pseudo_class.matches_in(context)
+            }
+            _ => self.matches(context.element),
+        }
+    }
+
+    /// Whether the selector looks at other elements than the subject, i.e. it has combinators
+    /// or structural pseudo-classes such as `:first-child`
+    pub fn is_contextual(&self) -> bool {
+        match self {
+            Selector::Child(..)
+            | Selector::Adjacent(..)
+            | Selector::GeneralSibling(..)
+            | Selector::Descendant(..) => true,
+            Selector::Pseudo(_, PseudoClass::FirstChild | PseudoClass::LastChild) => true,
+            Selector::Pseudo(_, PseudoClass::Not(box selector)) if selector.is_contextual() => true,
+            Selector::Class(Some(box left), _)
+            | Selector::Id(Some(box left), _)
+            | Selector::Pseudo(Some(box left), _) => left.is_contextual(),
+            _ => false,
+        }
+    }
+}
+
+impl PseudoClass {
+    /// Whether the element in the context matches the pseudo-class. The root element is neither
+    /// the first nor the last child, since it has no parent
+    fn matches_in(&self, context: &MatchContext) -> bool {
+        match self {
+            PseudoClass::Not(box selector) => !selector.matches_in(context),
+            PseudoClass::FirstChild => {
+                context.parent.is_some() && context.preceding_siblings().next().is_none()
+            }
+            PseudoClass::LastChild => context.parent.is_some() && !context.has_following_sibling(),
             _ => false,
         }
     }
@@ -539,7 +1983,57 @@ impl Selector {
 
 impl Declaration {
     pub fn new(property: DeclarationProperty, value: DeclarationValue) -> Self {
-        Self { property, value }
+        Self {
+            property,
+            value,
+            important: false,
+        }
+    }
+}
+
+impl Length {
+    /// Resolve the length to px. `em` is relative to font_size, `rem` to root_font_size,
+    /// and `%` to percent_base. Viewport units are not resolved and taken as px. `auto` is 0
+    ///
+    /// e.g. with font_size 20, root_font_size 16 and percent_base 300
+    ///   2em → 40, 2rem → 32, 50% → 150, 12pt → 16, 1in → 96
+    pub fn to_px(&self, font_size: f64, root_font_size: f64, percent_base: f64) -> f64 {
+        let (x, unit) = match self {
+            Length::Actual(x, unit) => (*x as f64, unit),
+            Length::Auto => return 0.0,
+        };
+        match unit {
+            Unit::Px => x,
+            Unit::Em => x * font_size,
+            Unit::Rem => x * root_font_size,
+            // x-height and width of `0` are about half of the font size
+            Unit::Ex | Unit::Ch => x * font_size / 2.0,
+            Unit::Pct => x * percent_base / 100.0,
+            // 1in = 96px
+            Unit::In => x * 96.0,
+            Unit::Cm => x * 96.0 / 2.54,
+            Unit::Mm => x * 96.0 / 25.4,
+            Unit::Q => x * 96.0 / 101.6,
+            Unit::Pt => x * 96.0 / 72.0,
+            Unit::Pc => x * 96.0 / 6.0,
+            Unit::Vh | Unit::Vw | Unit::Vmin | Unit::Vmax => x,
+        }
+    }
+}
+
+impl DeclarationProperty {
+    /// Whether the child element inherits the value of parent when it is not specified
+    pub fn is_inherited(&self) -> bool {
+        matches!(
+            self,
+            Self::Color
+                | Self::FontFamily
+                | Self::FontSize
+                | Self::FontWeight
+                | Self::LineHeight
+                | Self::TextAlign
+                | Self::Visibility
+        )
     }
 }
 
@@ -553,4 +2047,66 @@ impl Color {
     pub fn new(r: usize, g: usize, b: usize, a: usize) -> Self {
         Self { r, g, b, a }
     }
+
+    /// Parse hex notation `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`. `#` can be omitted
+    ///
+    /// e.g. #c00 → Color::new(0xcc, 0x00, 0x00, 0xff)
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |s: &str| usize::from_str_radix(s, 16).ok();
+        let (r, g, b, a) = match hex.len() {
+            3 | 4 => {
+                // each digit is doubled, e.g. c → cc
+                let digits = hex
+                    .chars()
+                    .map(|ch| channel(&ch.to_string()).map(|x| x * 0x11))
+                    .collect::<Option<Vec<_>>>()?;
+                let a = digits.get(3).copied().unwrap_or(0xff);
+                (digits[0], digits[1], digits[2], a)
+            }
+            6 | 8 => (
+                channel(&hex[0..2])?,
+                channel(&hex[2..4])?,
+                channel(&hex[4..6])?,
+                hex.get(6..8).map_or(Some(0xff), channel)?,
+            ),
+            _ => return None,
+        };
+        Some(Self::new(r, g, b, a))
+    }
+
+    /// Lowercase `#rrggbbaa`
+    pub fn to_hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+    }
+
+    /// Basic named colors
+    ///
+    /// e.g. red → Color::new(0xff, 0x00, 0x00, 0xff)
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (r, g, b) = match name.to_lowercase().as_str() {
+            "black" => (0x00, 0x00, 0x00),
+            "silver" => (0xc0, 0xc0, 0xc0),
+            "gray" | "grey" => (0x80, 0x80, 0x80),
+            "white" => (0xff, 0xff, 0xff),
+            "maroon" => (0x80, 0x00, 0x00),
+            "red" => (0xff, 0x00, 0x00),
+            "purple" => (0x80, 0x00, 0x80),
+            "fuchsia" => (0xff, 0x00, 0xff),
+            "green" => (0x00, 0x80, 0x00),
+            "lime" => (0x00, 0xff, 0x00),
+            "olive" => (0x80, 0x80, 0x00),
+            "yellow" => (0xff, 0xff, 0x00),
+            "navy" => (0x00, 0x00, 0x80),
+            "blue" => (0x00, 0x00, 0xff),
+            "teal" => (0x00, 0x80, 0x80),
+            "aqua" => (0x00, 0xff, 0xff),
+            "orange" => (0xff, 0xa5, 0x00),
+            _ => return None,
+        };
+        Some(Self::new(r, g, b, 0xff))
+    }
 }
@@ -1,25 +1,130 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::fmt::Debug;
 use std::iter::Peekable;
 use std::str::Chars;
-use super::ElementTagName;
+use super::{Element, ElementTagName, Node};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-/// TODO: ???
-pub type StyleMap = HashMap<DeclarationProperty, DeclarationValue>;
+/// Declarations of an element keyed by property. Ordered by property, so that iteration
+/// and debug output are the same across runs
+pub type StyleMap = BTreeMap<DeclarationProperty, DeclarationValue>;
 
 /// Parser that convert raw CSS input to CSSOM(StyleSheet)
-#[derive(Debug)]
 pub struct StyleSheetParser<'a> {
     pub(crate) input: Peekable<Chars<'a>>,
+    // length of input in characters, to report the position of errors
+    pub(crate) len: usize,
+    // errors of declarations and rules skipped by `parse` and `parse_inline`
+    pub(crate) errors: Vec<CssParseError>,
+    // content of the stylesheet which `@import` refers to, None ignores imports
+    pub(crate) fetcher: Option<ImportFetcher<'a>>,
+    // whether unknown properties are recorded in `warnings`
+    pub(crate) strict: bool,
+    pub(crate) warnings: Vec<CssParseWarning>,
 }
 
+/// Function which returns the content of stylesheet by URL of `@import`, or None if not found
+pub type ImportFetcher<'a> = Box<dyn FnMut(&str) -> Option<String> + 'a>;
+
 /// CSSOM. i.e. possess some CSS Rule
 #[derive(Default, PartialEq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(from = "StyleSheetData")
+)]
 pub struct StyleSheet {
     pub(crate) rules: Vec<Rule>,
-    // TODO: impl better
-    pub(crate) media_query: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    pub(crate) index: RuleIndex,
+    // e.g. utf-8 of the leading `@charset "utf-8";`, which is only informational
+    pub(crate) charset: Option<String>,
+}
+
+/// Serialized form of StyleSheet. RuleIndex is rebuilt on deserialization
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct StyleSheetData {
+    rules: Vec<Rule>,
+    #[serde(default)]
+    charset: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+impl From<StyleSheetData> for StyleSheet {
+    fn from(data: StyleSheetData) -> Self {
+        let mut stylesheet = Self::new(data.rules);
+        stylesheet.charset = data.charset;
+        stylesheet
+    }
+}
+
+/// Computes StyleMap of elements with owned StyleSheet, optionally memoizing the result
+#[derive(Debug)]
+pub struct StyleEngine {
+    pub(crate) stylesheet: StyleSheet,
+    // default styles of the browser, which the author's stylesheet overrides
+    pub(crate) user_agent: StyleSheet,
+    // normal and `!important` styles from the stylesheets
+    pub(crate) cache: Option<RefCell<HashMap<ElementSignature, (StyleMap, StyleMap)>>>,
+}
+
+/// Element and where it is in DOM, for combinators which look at its parent and siblings
+#[derive(Debug, Clone, Copy)]
+pub struct MatchContext<'a> {
+    pub element: &'a Element,
+    // None for the root element
+    pub parent: Option<&'a MatchContext<'a>>,
+    // children of the parent including the element, which is at `index`
+    pub siblings: &'a [Node],
+    pub index: usize,
+}
+
+/// Key of StyleEngine cache. Selectors without combinators only look at tag name, classes and id of element
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub(crate) struct ElementSignature {
+    pub(crate) tag_name: ElementTagName,
+    // sorted
+    pub(crate) classes: Vec<String>,
+    pub(crate) id: Option<String>,
+}
+
+/// Index of rules keyed by tag name, id and class of the rightmost selector.
+/// Rules are stored by their position in `StyleSheet::rules`
+#[derive(Debug, Default, PartialEq, Clone)]
+pub(crate) struct RuleIndex {
+    pub(crate) by_tag: HashMap<ElementTagName, Vec<usize>>,
+    pub(crate) by_id: HashMap<String, Vec<usize>>,
+    pub(crate) by_class: HashMap<String, Vec<usize>>,
+    // selectors which cannot be keyed, e.g. :link
+    pub(crate) universal: Vec<usize>,
+}
+
+/// Error of parsing CSS
+#[derive(Debug, PartialEq, Clone)]
+pub enum CssParseError {
+    // e.g. `*` of `div > *`, and its position
+    UnexpectedChar(char, usize),
+    UnexpectedEof,
+    // e.g. `div.` which has no class name
+    ExpectedIdentifier(usize),
+    // e.g. `color` of `color: ###`, whose value cannot be parsed
+    InvalidDeclaration(String, usize),
+    // e.g. `div` of `div { color: red` which has no closing `}`
+    UnterminatedRule(String),
+    // e.g. `div` of `div ` which has no declaration block
+    UnterminatedSelector(String),
+}
+
+/// Problem of CSS which doesn't stop parsing, reported in strict mode
+#[derive(Debug, PartialEq, Clone)]
+pub enum CssParseWarning {
+    // e.g. `colr` of `colr: red`, and its position. The declaration is kept as `Other`
+    UnknownProperty(String, usize),
 }
 
 /// CSS Rule.
@@ -27,17 +132,47 @@ pub struct StyleSheet {
 ///   margin: auto; color: #cc0000
 /// }
 #[derive(Default, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Rule {
     // h1, h2, h3, div.note, #answer
     pub(crate) selectors: Vec<Selector>,
     // { margin: auto; color: #cc0000; }
     pub(crate) declarations: Vec<Declaration>,
+    // @media screen and (max-width: 700px) which encloses the rule
+    pub(crate) media: Option<MediaQuery>,
+}
+
+/// Condition of `@media`. Matches if any of the comma separated groups matches,
+/// and a group matches if all of its conditions joined by `and` match
+/// e.g.
+///   screen and (max-width: 700px), print
+#[derive(Default, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MediaQuery {
+    pub(crate) queries: Vec<Vec<MediaCondition>>,
+}
+
+/// One condition of a media query group
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MediaCondition {
+    // screen, print, all. The page is rendered on screen, so only screen and all match
+    MediaType(String),
+    // (min-width: 700px)
+    MinWidth(Length),
+    // (max-width: 700px)
+    MaxWidth(Length),
+    // (orientation: landscape), etc. never matches
+    Other(String),
+    // a group after `not`, e.g. print and (max-width: 700px) of `not print and (max-width: 700px)`
+    Not(Vec<MediaCondition>),
 }
 
 /// CSS Selector
 /// e.g.
-///   h1, .note, #modal, div > p, h1 + p
-#[derive(PartialEq, Clone)]
+///   h1, .note, #modal, div > p, h1 + p, h1 ~ p, article p
+#[derive(PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Selector {
     // h1, div, etc.
     Tag(ElementTagName),
@@ -49,7 +184,11 @@ pub enum Selector {
     Child(Box<Selector>, Box<Selector>),
     // h1 + p
     Adjacent(Box<Selector>, Box<Selector>),
-    // a:link, a:visited
+    // h1 ~ p
+    GeneralSibling(Box<Selector>, Box<Selector>),
+    // article p
+    Descendant(Box<Selector>, Box<Selector>),
+    // a:link, a:visited, p:not(.special)
     Pseudo(Option<Box<Selector>>, PseudoClass),
     // @media (max-width: 700px)
 }
@@ -60,13 +199,20 @@ pub enum Selector {
 ///   div: #cc0000
 ///   display: none
 #[derive(Default, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Declaration {
     pub property: DeclarationProperty,
     // margin, padding, display, etc.
     pub value: DeclarationValue, // #cc0000, 10px, etc.
+    // followed by `!important`
+    pub important: bool,
 }
 
-#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+/// CSS property
+///
+/// Properties are ordered as the variants are declared, e.g. in StyleMap and debug output
+#[derive(PartialEq, Eq, Hash, Debug, Clone, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum DeclarationProperty {
     Margin,
     MarginLeft,
@@ -80,13 +226,38 @@ pub enum DeclarationProperty {
     PaddingBottom,
     Width,
     Height,
+    MinWidth,
+    MaxWidth,
+    MinHeight,
+    MaxHeight,
     Display,
     Color,
     BackgroundColor,
     BorderRadius,
+    BorderTopLeftRadius,
+    BorderTopRightRadius,
+    BorderBottomRightRadius,
+    BorderBottomLeftRadius,
     TextDecoration,
     BoxShadow,
+    Font,
     FontFamily,
+    FontSize,
+    FontWeight,
+    LineHeight,
+    TextAlign,
+    Opacity,
+    Overflow,
+    Visibility,
+    Position,
+    Flex,
+    FlexDirection,
+    JustifyContent,
+    GridTemplateColumns,
+    Content,
+    Transition,
+    Animation,
+    // keep it last, so that unknown properties follow the known ones ordered by name
     Other(String),
 }
 
@@ -105,13 +276,37 @@ impl<'a> From<&'a str> for DeclarationProperty {
             "padding-bottom" => Self::PaddingBottom,
             "width" => Self::Width,
             "height" => Self::Height,
+            "min-width" => Self::MinWidth,
+            "max-width" => Self::MaxWidth,
+            "min-height" => Self::MinHeight,
+            "max-height" => Self::MaxHeight,
             "display" => Self::Display,
             "color" => Self::Color,
             "background-color" => Self::BackgroundColor,
             "border-radius" => Self::BorderRadius,
+            "border-top-left-radius" => Self::BorderTopLeftRadius,
+            "border-top-right-radius" => Self::BorderTopRightRadius,
+            "border-bottom-right-radius" => Self::BorderBottomRightRadius,
+            "border-bottom-left-radius" => Self::BorderBottomLeftRadius,
             "text-decoration" => Self::TextDecoration,
             "box-shadow" => Self::BoxShadow,
+            "font" => Self::Font,
             "font-family" => Self::FontFamily,
+            "font-size" => Self::FontSize,
+            "font-weight" => Self::FontWeight,
+            "line-height" => Self::LineHeight,
+            "text-align" => Self::TextAlign,
+            "opacity" => Self::Opacity,
+            "overflow" => Self::Overflow,
+            "visibility" => Self::Visibility,
+            "position" => Self::Position,
+            "flex" => Self::Flex,
+            "flex-direction" => Self::FlexDirection,
+            "justify-content" => Self::JustifyContent,
+            "grid-template-columns" => Self::GridTemplateColumns,
+            "content" => Self::Content,
+            "transition" => Self::Transition,
+            "animation" => Self::Animation,
             _ => Self::Other(property_name.to_string()),
         }
     }
@@ -125,7 +320,9 @@ impl<'a> From<&'a str> for Display {
             "inline" => Self::Inline,
             "inline-block" => Self::InlineBlock,
             "flex" => Self::Flex,
-            _ => Self::Block,
+            "grid" => Self::Grid,
+            // initial value of `display`
+            _ => Self::Inline,
         }
     }
 }
@@ -140,6 +337,76 @@ impl<'a> From<&'a str> for TextDecoration {
     }
 }
 
+impl<'a> From<&'a str> for TextAlign {
+    fn from(key: &'a str) -> Self {
+        match key {
+            "center" => Self::Center,
+            "right" | "end" => Self::Right,
+            "justify" => Self::Justify,
+            _ => Self::Left,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Overflow {
+    fn from(key: &'a str) -> Self {
+        match key {
+            "hidden" | "clip" => Self::Hidden,
+            "scroll" => Self::Scroll,
+            "auto" => Self::Auto,
+            // initial value of `overflow`
+            _ => Self::Visible,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Visibility {
+    fn from(key: &'a str) -> Self {
+        match key {
+            "hidden" => Self::Hidden,
+            "collapse" => Self::Collapse,
+            // initial value of `visibility`
+            _ => Self::Visible,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Position {
+    fn from(key: &'a str) -> Self {
+        match key {
+            "relative" => Self::Relative,
+            "absolute" => Self::Absolute,
+            "fixed" => Self::Fixed,
+            "sticky" => Self::Sticky,
+            // initial value of `position`
+            _ => Self::Static,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for FlexDirection {
+    fn from(key: &'a str) -> Self {
+        match key {
+            "column" | "column-reverse" => Self::Column,
+            // initial value of `flex-direction`
+            _ => Self::Row,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for JustifyContent {
+    fn from(key: &'a str) -> Self {
+        match key {
+            "flex-end" | "end" | "right" => Self::FlexEnd,
+            "center" => Self::Center,
+            "space-between" => Self::SpaceBetween,
+            "space-around" => Self::SpaceAround,
+            // initial value of `justify-content` behaves as flex-start
+            _ => Self::FlexStart,
+        }
+    }
+}
+
 impl Default for DeclarationProperty {
     fn default() -> Self {
         Self::Display
@@ -148,6 +415,7 @@ impl Default for DeclarationProperty {
 
 /// CSS declaration value
 #[derive(PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum DeclarationValue {
     // #cc0000
     Color(Color),
@@ -155,18 +423,61 @@ pub enum DeclarationValue {
     Display(Display),
     TextDecoration(TextDecoration),
     BoxShadow(BoxShadow),
+    LineHeight(LineHeight),
+    // numeric weight, e.g. 400 for normal, 700 for bold
+    FontWeight(u16),
+    TextAlign(TextAlign),
+    // unitless number, e.g. 0.5 of `opacity`, flex-grow of `flex`
+    Number(f32),
+    Overflow(Overflow),
+    Visibility(Visibility),
+    Position(Position),
+    FlexDirection(FlexDirection),
+    JustifyContent(JustifyContent),
+    // columns of `grid-template-columns`
+    TrackList(Vec<TrackSize>),
+    // quoted string without its quotes, e.g. hello of `content: "hello"`
+    String(String),
     Other(String),
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Length {
     Actual(f32, Unit),
     Auto,
 }
 
+/// Value of `line-height`
+///
+/// e.g.
+///   normal → LineHeight::Normal
+///   1.5 → LineHeight::Number(1.5), multiplied by the font size
+///   24px → LineHeight::Length(Length::Actual(24.0, Unit::Px))
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LineHeight {
+    Normal,
+    Number(f32),
+    Length(Length),
+}
+
+/// Size of a grid track, i.e. a column of `grid-template-columns`
+///
+/// e.g.
+///   100px → TrackSize::Length(Length::Actual(100.0, Unit::Px))
+///   1fr → TrackSize::Fr(1.0), a share of the space left by the other tracks
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TrackSize {
+    Length(Length),
+    Fr(f32),
+}
+
 /// Unit of CSS declaration value
 #[allow(dead_code)]
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Unit {
     Em,
     Ex,
@@ -187,16 +498,21 @@ pub enum Unit {
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Display {
     None,
     Block,
     Inline,
     InlineBlock,
     Flex,
+    Grid,
 }
 
 /// Color of CSS declaration value
+///
+/// Each channel is in 0..=255, and `a` is 0xff when opaque
 #[derive(Default, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Color {
     pub r: usize,
     pub g: usize,
@@ -205,12 +521,67 @@ pub struct Color {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum TextDecoration {
     None,
     Underline,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Overflow {
+    Visible,
+    Hidden,
+    Scroll,
+    Auto,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Visibility {
+    Visible,
+    Hidden,
+    Collapse,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FlexDirection {
+    Row,
+    Column,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum JustifyContent {
+    FlexStart,
+    FlexEnd,
+    Center,
+    SpaceBetween,
+    SpaceAround,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    Sticky,
+}
+
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct BoxShadow {
     pub offset_x: Length,
     pub offset_y: Length,
@@ -219,10 +590,16 @@ pub struct BoxShadow {
     pub color: Color,
 }
 
-#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum PseudoClass {
     Link,
     Visited,
+    // :not(.special), which matches elements the selector doesn't match
+    Not(Box<Selector>),
+    // the first and the last element among siblings
+    FirstChild,
+    LastChild,
     // TODO: impl others...
     Other(String),
 }
@@ -232,11 +609,61 @@ impl<'a> From<&'a str> for PseudoClass {
         match pseudo_class {
             "link" => Self::Link,
             "visited" => Self::Visited,
+            "first-child" => Self::FirstChild,
+            "last-child" => Self::LastChild,
             _ => Self::Other(pseudo_class.to_string()),
         }
     }
 }
 
+impl fmt::Display for CssParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CssParseError::UnexpectedChar(ch, position) => {
+                write!(f, "unexpected character '{}' at {}", ch, position)
+            }
+            CssParseError::UnexpectedEof => write!(f, "unexpected end of input"),
+            CssParseError::ExpectedIdentifier(position) => {
+                write!(f, "expected identifier at {}", position)
+            }
+            CssParseError::InvalidDeclaration(property, position) => {
+                write!(f, "invalid value of `{}` at {}", property, position)
+            }
+            CssParseError::UnterminatedRule(selector) => {
+                write!(f, "rule `{}` is not closed by '}}'", selector)
+            }
+            CssParseError::UnterminatedSelector(selector) => {
+                write!(f, "selector `{}` has no declaration block", selector)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CssParseError {}
+
+impl fmt::Display for CssParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CssParseWarning::UnknownProperty(property, position) => {
+                write!(f, "unknown property `{}` at {}", property, position)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for StyleSheetParser<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StyleSheetParser")
+            .field("input", &self.input)
+            .field("len", &self.len)
+            .field("errors", &self.errors)
+            .field("fetcher", &self.fetcher.is_some())
+            .field("strict", &self.strict)
+            .field("warnings", &self.warnings)
+            .finish()
+    }
+}
+
 impl fmt::Debug for StyleSheet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut rules = String::new();
@@ -269,7 +696,10 @@ impl fmt::Debug for Rule {
             declarations.push('\n');
         }
 
-        write!(f, "{} {{\n{}}}", selectors, declarations)
+        match &self.media {
+            Some(media) => write!(f, "{:?} {} {{\n{}}}", media, selectors, declarations),
+            None => write!(f, "{} {{\n{}}}", selectors, declarations),
+        }
     }
 }
 
@@ -287,6 +717,8 @@ impl fmt::Debug for Selector {
             },
             Selector::Child(p, c) => write!(f, "{:?} > {:?}", p, c),
             Selector::Adjacent(l, r) => write!(f, "{:?} + {:?}", l, r),
+            Selector::GeneralSibling(l, r) => write!(f, "{:?} ~ {:?}", l, r),
+            Selector::Descendant(a, d) => write!(f, "{:?} {:?}", a, d),
             Selector::Pseudo(tag, pc) => match tag {
                 Some(selector) => write!(f, "{:?}:{:?}", selector, pc),
                 None => write!(f, "#{:?}", pc),
@@ -295,9 +727,59 @@ impl fmt::Debug for Selector {
     }
 }
 
+/// Selectors are ordered by specificity, and by their structure when the specificity is same,
+/// so that only equal selectors compare as equal
+impl Ord for Selector {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.specificity()
+            .cmp(&other.specificity())
+            .then_with(|| self.cmp_structure(other))
+    }
+}
+
+impl Selector {
+    fn cmp_structure(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Tag(l), Self::Tag(r)) => l.cmp(r),
+            (Self::Class(ls, l), Self::Class(rs, r)) | (Self::Id(ls, l), Self::Id(rs, r)) => {
+                (ls, l).cmp(&(rs, r))
+            }
+            (Self::Child(l1, l2), Self::Child(r1, r2))
+            | (Self::Adjacent(l1, l2), Self::Adjacent(r1, r2))
+            | (Self::GeneralSibling(l1, l2), Self::GeneralSibling(r1, r2))
+            | (Self::Descendant(l1, l2), Self::Descendant(r1, r2)) => (l1, l2).cmp(&(r1, r2)),
+            (Self::Pseudo(ls, l), Self::Pseudo(rs, r)) => (ls, l).cmp(&(rs, r)),
+            _ => self.kind_index().cmp(&other.kind_index()),
+        }
+    }
+
+    fn kind_index(&self) -> usize {
+        match self {
+            Self::Tag(_) => 0,
+            Self::Class(..) => 1,
+            Self::Id(..) => 2,
+            Self::Child(..) => 3,
+            Self::Adjacent(..) => 4,
+            Self::GeneralSibling(..) => 5,
+            Self::Descendant(..) => 6,
+            Self::Pseudo(..) => 7,
+        }
+    }
+}
+
+impl PartialOrd for Selector {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl fmt::Debug for Declaration {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}: {:?}", self.property, self.value)
+        write!(f, "{:?}: {:?}", self.property, self.value)?;
+        if self.important {
+            write!(f, " !important")?;
+        }
+        Ok(())
     }
 }
 
@@ -315,6 +797,17 @@ impl fmt::Debug for DeclarationValue {
             DeclarationValue::Display(ref v) => write!(f, "{:?}", v),
             DeclarationValue::TextDecoration(ref v) => write!(f, "{:?}", v),
             DeclarationValue::BoxShadow(ref v) => write!(f, "{:?}", v),
+            DeclarationValue::LineHeight(ref v) => write!(f, "{:?}", v),
+            DeclarationValue::FontWeight(ref v) => write!(f, "{:?}", v),
+            DeclarationValue::TextAlign(ref v) => write!(f, "{:?}", v),
+            DeclarationValue::Number(ref v) => write!(f, "{:?}", v),
+            DeclarationValue::Overflow(ref v) => write!(f, "{:?}", v),
+            DeclarationValue::Visibility(ref v) => write!(f, "{:?}", v),
+            DeclarationValue::Position(ref v) => write!(f, "{:?}", v),
+            DeclarationValue::FlexDirection(ref v) => write!(f, "{:?}", v),
+            DeclarationValue::JustifyContent(ref v) => write!(f, "{:?}", v),
+            DeclarationValue::TrackList(ref v) => write!(f, "{:?}", v),
+            DeclarationValue::String(ref s) => write!(f, "{:?}", s),
             DeclarationValue::Other(ref s) => write!(f, "{:?}", s),
         }
     }
@@ -325,3 +818,391 @@ impl fmt::Debug for Color {
         write!(f, "r: {} g: {} b: {} a: {}", self.r, self.g, self.b, self.a)
     }
 }
+
+/// CSS text which can be parsed by `StyleSheetParser` again. A rule in `@media` is emitted in its own block
+impl fmt::Display for StyleSheet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut rules = String::new();
+        if let Some(charset) = &self.charset {
+            rules.push_str(&format!("@charset \"{}\";", charset));
+        }
+        for rule in &self.rules {
+            if !rules.is_empty() {
+                rules.push_str("\n\n");
+            }
+            rules.push_str(&rule.to_string());
+        }
+        write!(f, "{}", rules)
+    }
+}
+
+impl fmt::Display for MediaQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let queries = self
+            .queries
+            .iter()
+            .map(|conditions| {
+                conditions
+                    .iter()
+                    .map(|condition| condition.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" and ")
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", queries.join(", "))
+    }
+}
+
+impl fmt::Debug for MediaQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "@media {}", self)
+    }
+}
+
+impl fmt::Display for MediaCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MediaCondition::MediaType(media_type) => write!(f, "{}", media_type),
+            MediaCondition::MinWidth(length) => write!(f, "(min-width: {})", length),
+            MediaCondition::MaxWidth(length) => write!(f, "(max-width: {})", length),
+            MediaCondition::Other(feature) => write!(f, "({})", feature),
+            MediaCondition::Not(conditions) => {
+                let conditions = conditions
+                    .iter()
+                    .map(|condition| condition.to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "not {}", conditions.join(" and "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut selectors = String::new();
+        let mut declarations = String::new();
+        let indent = "    ";
+
+        for selector in &self.selectors {
+            if !selectors.is_empty() {
+                selectors.push_str(", ");
+            }
+            selectors.push_str(&selector.to_string());
+        }
+
+        for declaration in &self.declarations {
+            declarations.push_str(indent);
+            declarations.push_str(&format!("{};", declaration));
+            declarations.push('\n');
+        }
+
+        match &self.media {
+            Some(media) => write!(
+                f,
+                "@media {} {{\n{} {{\n{}}}\n}}",
+                media, selectors, declarations
+            ),
+            None => write!(f, "{} {{\n{}}}", selectors, declarations),
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Selector::Tag(ref tag) => write!(f, "{}", tag),
+            Selector::Class(s, class) => match s {
+                Some(selector) => write!(f, "{}.{}", selector, class),
+                None => write!(f, ".{}", class),
+            },
+            Selector::Id(s, id) => match s {
+                Some(selector) => write!(f, "{}#{}", selector, id),
+                None => write!(f, "#{}", id),
+            },
+            Selector::Child(p, c) => write!(f, "{} > {}", p, c),
+            Selector::Adjacent(l, r) => write!(f, "{} + {}", l, r),
+            Selector::GeneralSibling(l, r) => write!(f, "{} ~ {}", l, r),
+            Selector::Descendant(a, d) => write!(f, "{} {}", a, d),
+            Selector::Pseudo(tag, pc) => match tag {
+                Some(selector) => write!(f, "{}:{}", selector, pc),
+                None => write!(f, ":{}", pc),
+            },
+        }
+    }
+}
+
+impl fmt::Display for PseudoClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PseudoClass::Link => write!(f, "link"),
+            PseudoClass::Visited => write!(f, "visited"),
+            PseudoClass::Not(selector) => write!(f, "not({})", selector),
+            PseudoClass::FirstChild => write!(f, "first-child"),
+            PseudoClass::LastChild => write!(f, "last-child"),
+            PseudoClass::Other(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.property, self.value)?;
+        if self.important {
+            write!(f, " !important")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DeclarationProperty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Self::Margin => "margin",
+            Self::MarginLeft => "margin-left",
+            Self::MarginRight => "margin-right",
+            Self::MarginTop => "margin-top",
+            Self::MarginBottom => "margin-bottom",
+            Self::Padding => "padding",
+            Self::PaddingLeft => "padding-left",
+            Self::PaddingRight => "padding-right",
+            Self::PaddingTop => "padding-top",
+            Self::PaddingBottom => "padding-bottom",
+            Self::Width => "width",
+            Self::Height => "height",
+            Self::MinWidth => "min-width",
+            Self::MaxWidth => "max-width",
+            Self::MinHeight => "min-height",
+            Self::MaxHeight => "max-height",
+            Self::Display => "display",
+            Self::Color => "color",
+            Self::BackgroundColor => "background-color",
+            Self::BorderRadius => "border-radius",
+            Self::BorderTopLeftRadius => "border-top-left-radius",
+            Self::BorderTopRightRadius => "border-top-right-radius",
+            Self::BorderBottomRightRadius => "border-bottom-right-radius",
+            Self::BorderBottomLeftRadius => "border-bottom-left-radius",
+            Self::TextDecoration => "text-decoration",
+            Self::BoxShadow => "box-shadow",
+            Self::Font => "font",
+            Self::FontFamily => "font-family",
+            Self::FontSize => "font-size",
+            Self::FontWeight => "font-weight",
+            Self::LineHeight => "line-height",
+            Self::TextAlign => "text-align",
+            Self::Opacity => "opacity",
+            Self::Overflow => "overflow",
+            Self::Visibility => "visibility",
+            Self::Position => "position",
+            Self::Flex => "flex",
+            Self::FlexDirection => "flex-direction",
+            Self::JustifyContent => "justify-content",
+            Self::GridTemplateColumns => "grid-template-columns",
+            Self::Content => "content",
+            Self::Transition => "transition",
+            Self::Animation => "animation",
+            Self::Other(s) => s,
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for DeclarationValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            DeclarationValue::Color(ref color) => write!(f, "{}", color),
+            DeclarationValue::Length(ref length) => write!(f, "{}", length),
+            DeclarationValue::Display(ref v) => write!(f, "{}", v),
+            DeclarationValue::TextDecoration(ref v) => write!(f, "{}", v),
+            DeclarationValue::BoxShadow(ref v) => write!(f, "{}", v),
+            DeclarationValue::LineHeight(ref v) => write!(f, "{}", v),
+            DeclarationValue::FontWeight(ref v) => write!(f, "{}", v),
+            DeclarationValue::TextAlign(ref v) => write!(f, "{}", v),
+            DeclarationValue::Number(ref v) => write!(f, "{}", v),
+            DeclarationValue::Overflow(ref v) => write!(f, "{}", v),
+            DeclarationValue::Visibility(ref v) => write!(f, "{}", v),
+            DeclarationValue::Position(ref v) => write!(f, "{}", v),
+            DeclarationValue::FlexDirection(ref v) => write!(f, "{}", v),
+            DeclarationValue::JustifyContent(ref v) => write!(f, "{}", v),
+            DeclarationValue::TrackList(ref v) => {
+                let tracks = v.iter().map(|track| track.to_string()).collect::<Vec<_>>();
+                write!(f, "{}", tracks.join(" "))
+            }
+            DeclarationValue::String(ref s) => {
+                write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+            DeclarationValue::Other(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Length::Actual(x, unit) => write!(f, "{}{}", x, unit),
+            Length::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl fmt::Display for LineHeight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineHeight::Normal => write!(f, "normal"),
+            LineHeight::Number(x) => write!(f, "{}", x),
+            LineHeight::Length(length) => write!(f, "{}", length),
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Unit::Em => "em",
+            Unit::Ex => "ex",
+            Unit::Ch => "ch",
+            Unit::Rem => "rem",
+            Unit::Vh => "vh",
+            Unit::Vw => "vw",
+            Unit::Vmin => "vmin",
+            Unit::Vmax => "vmax",
+            Unit::Px => "px",
+            Unit::Mm => "mm",
+            Unit::Q => "q",
+            Unit::Cm => "cm",
+            Unit::In => "in",
+            Unit::Pt => "pt",
+            Unit::Pc => "pc",
+            Unit::Pct => "%",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Display::None => "none",
+            Display::Block => "block",
+            Display::Inline => "inline",
+            Display::InlineBlock => "inline-block",
+            Display::Flex => "flex",
+            Display::Grid => "grid",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for TextDecoration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            TextDecoration::None => "none",
+            TextDecoration::Underline => "underline",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for TextAlign {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            TextAlign::Left => "left",
+            TextAlign::Center => "center",
+            TextAlign::Right => "right",
+            TextAlign::Justify => "justify",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Overflow::Visible => "visible",
+            Overflow::Hidden => "hidden",
+            Overflow::Scroll => "scroll",
+            Overflow::Auto => "auto",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Visibility::Visible => "visible",
+            Visibility::Hidden => "hidden",
+            Visibility::Collapse => "collapse",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for FlexDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            FlexDirection::Row => "row",
+            FlexDirection::Column => "column",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for JustifyContent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            JustifyContent::FlexStart => "flex-start",
+            JustifyContent::FlexEnd => "flex-end",
+            JustifyContent::Center => "center",
+            JustifyContent::SpaceBetween => "space-between",
+            JustifyContent::SpaceAround => "space-around",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for TrackSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrackSize::Length(length) => write!(f, "{}", length),
+            TrackSize::Fr(fr) => write!(f, "{}fr", fr),
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Position::Static => "static",
+            Position::Relative => "relative",
+            Position::Absolute => "absolute",
+            Position::Fixed => "fixed",
+            Position::Sticky => "sticky",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for BoxShadow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Color { r, g, b, a } = self.color;
+        write!(
+            f,
+            "{} {} {} {} rgba({}, {}, {}, {})",
+            self.offset_x,
+            self.offset_y,
+            self.blur_radius,
+            self.spread_radius,
+            r,
+            g,
+            b,
+            a as f32 / 255.0
+        )
+    }
+}
+
+/// #rrggbbaa
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
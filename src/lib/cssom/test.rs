@@ -161,7 +161,7 @@
 //         )];
 //         let rule2 = Rule::new(selectors, declarations);
 //
-//         let expect = StyleSheet::new(vec![rule1, rule2], None);
+//         let expect = StyleSheet::new(vec![rule1, rule2]);
 //         assert_eq!(parser.parse(), expect);
 //     }
 //
@@ -442,3 +442,1125 @@
 //         assert_eq!(parser.input.next().unwrap(), 'x');
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::*;
+
+    #[test]
+    fn test_unitless_length() {
+        let mut parser = StyleSheetParser::new("width: 10; height: 0; padding-left: 5px");
+        assert_eq!(
+            parser.parse_inline(),
+            vec![
+                Declaration::new(
+                    DeclarationProperty::Height,
+                    DeclarationValue::Length(Length::Actual(0.0, Unit::Px))
+                ),
+                Declaration::new(
+                    DeclarationProperty::PaddingLeft,
+                    DeclarationValue::Length(Length::Actual(5.0, Unit::Px))
+                )
+            ]
+        );
+        assert_eq!(parser.errors().len(), 1);
+    }
+
+    #[test]
+    fn test_malformed_number() {
+        let mut parser = StyleSheetParser::new(
+            "height: 1.2.3px; margin-top: .; margin-left: -.; opacity: ..5; padding-left: 5px",
+        );
+        assert_eq!(
+            parser.parse_inline(),
+            vec![Declaration::new(
+                DeclarationProperty::PaddingLeft,
+                DeclarationValue::Length(Length::Actual(5.0, Unit::Px))
+            )]
+        );
+        assert_eq!(parser.errors().len(), 4);
+    }
+
+    #[test]
+    fn test_rules() {
+        let stylesheet = StyleSheetParser::new("h1, p { color: red } p { margin: 0 }")
+            .parse()
+            .unwrap();
+        let counts = stylesheet
+            .rules()
+            .iter()
+            .map(|rule| (rule.selectors().len(), rule.declarations().len()))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![(2, 1), (1, 4)]);
+    }
+
+    #[test]
+    fn test_media_not_only() {
+        let stylesheet = StyleSheetParser::new(
+            "@media not print { p { color: red } } \
+             @media only screen and (max-width: 600px) { p { margin: 0 } } \
+             @media not screen and (max-width: 600px) { p { opacity: 0.5 } }",
+        )
+        .parse()
+        .unwrap();
+        let media_queries = stylesheet
+            .rules()
+            .iter()
+            .filter_map(|rule| rule.media.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            media_queries
+                .iter()
+                .map(|media_query| media_query.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "not print",
+                "screen and (max-width: 600px)",
+                "not screen and (max-width: 600px)"
+            ]
+        );
+        assert_eq!(
+            media_queries
+                .iter()
+                .map(|media_query| media_query.matches(500.0))
+                .collect::<Vec<_>>(),
+            vec![true, true, false]
+        );
+        assert_eq!(
+            media_queries
+                .iter()
+                .map(|media_query| media_query.matches(800.0))
+                .collect::<Vec<_>>(),
+            vec![true, false, true]
+        );
+    }
+
+    #[test]
+    fn test_skip_malformed_rule() {
+        let css = "div.. { color: red } p { color: ### } h1 > { margin: 0 } h2 { display: block }";
+        let mut parser = StyleSheetParser::new(css);
+        let stylesheet = parser.parse().unwrap();
+        assert_eq!(
+            stylesheet.to_string(),
+            "p {\n}\n\nh2 {\n    display: block;\n}"
+        );
+        assert_eq!(
+            parser.errors(),
+            &[
+                CssParseError::ExpectedIdentifier(4),
+                CssParseError::InvalidDeclaration("color".to_string(), 25),
+                CssParseError::UnexpectedChar('{', 43),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_color_keyword() {
+        let mut parser = StyleSheetParser::new(
+            "color: blu; background-color: inherit; color: currentColor; \
+             background-color: Transparent; color: RED",
+        );
+        assert_eq!(
+            parser.parse_inline(),
+            vec![
+                Declaration::new(
+                    DeclarationProperty::BackgroundColor,
+                    DeclarationValue::Color(Color::new(0x00, 0x00, 0x00, 0x00))
+                ),
+                Declaration::new(
+                    DeclarationProperty::Color,
+                    DeclarationValue::Color(Color::new(0xff, 0x00, 0x00, 0xff))
+                ),
+            ]
+        );
+        assert_eq!(parser.errors().len(), 3);
+    }
+
+    #[test]
+    fn test_rule_index_same_as_scan() {
+        let css = (0..100)
+            .map(|i| {
+                format!(
+                    "p {{ margin-left: {i}px }} .c{m} {{ margin-top: {i}px }} #id{m} {{ color: red }} \
+                     * {{ padding-left: {i}px }} div > .c{m} {{ padding-top: {i}px }}",
+                    i = i,
+                    m = i % 7
+                )
+            })
+            .collect::<String>();
+        let stylesheet = StyleSheetParser::new(&css).parse().unwrap();
+        let dom = DocumentObjectParser::new(
+            r#"<div><p class="c1 c3" id="id3">a</p><span class="c6">b</span><p>c</p></div>"#,
+        )
+        .parse()
+        .unwrap();
+        let div = match &dom {
+            Node::Element(div) => div,
+            _ => unreachable!(),
+        };
+        let parent = MatchContext::new(div);
+        for (index, child) in div.children.iter().enumerate() {
+            let element = match child {
+                Node::Element(element) => element,
+                _ => continue,
+            };
+            let context = MatchContext {
+                element,
+                parent: Some(&parent),
+                siblings: &div.children,
+                index,
+            };
+            // every rule in source order without the index
+            let mut expected = StyleMap::new();
+            for rule in stylesheet.rules() {
+                if rule.selectors().iter().any(|s| s.matches_in(&context)) {
+                    expected.extend(rule.get_styles(false));
+                }
+            }
+            assert_eq!(stylesheet.get_styles(&context), expected);
+        }
+    }
+
+    #[test]
+    fn test_style_engine_cache() {
+        let css =
+            "li { margin-left: 4px } .done { color: gray } li:first-child { margin-top: 1px }";
+        let html = format!(
+            "<ul>{}</ul>",
+            r#"<li class="done">a</li><li>b</li>"#.repeat(50)
+        );
+        let dom = DocumentObjectParser::new(&html).parse().unwrap();
+        let stylesheet = StyleSheetParser::new(css).parse().unwrap();
+        let cached = RenderObject::build(&dom, &StyleEngine::new(stylesheet.clone())).unwrap();
+        let uncached = RenderObject::build(&dom, &StyleEngine::without_cache(stylesheet)).unwrap();
+        assert_eq!(cached.debug_computed(), uncached.debug_computed());
+        // :first-child depends on siblings, so it isn't shared by the cache
+        assert_eq!(
+            cached.children[0].get_length(&DeclarationProperty::MarginTop),
+            1.0
+        );
+        assert_eq!(
+            cached.children[2].get_length(&DeclarationProperty::MarginTop),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let css = r#"
+@charset "utf-8";
+h1, div.note > p:first-child, #main ~ span:not(.x) {
+    margin: 0 auto;
+    padding-left: 1.5em;
+    color: rgba(255, 0, 0, 0.5);
+    font-family: "Times New Roman", serif;
+    content: "a, b";
+}
+@media screen and (max-width: 600px) {
+    p { display: none; opacity: 0.5 !important }
+}
+"#;
+        let stylesheet = StyleSheetParser::new(css).parse().unwrap();
+        let printed = stylesheet.to_string();
+        assert_eq!(StyleSheetParser::new(&printed).parse(), Ok(stylesheet));
+    }
+
+    #[test]
+    fn test_selector_parse() {
+        use ElementTagName::*;
+        assert_eq!(
+            Selector::parse("div.note"),
+            Ok(Selector::Class(
+                Some(Box::new(Selector::Tag(Div))),
+                "note".to_string()
+            ))
+        );
+        assert_eq!(
+            Selector::parse("#id"),
+            Ok(Selector::Id(None, "id".to_string()))
+        );
+        assert_eq!(
+            Selector::parse("article > p"),
+            Ok(Selector::Child(
+                Box::new(Selector::Tag(Other("article".to_string()))),
+                Box::new(Selector::Tag(P))
+            ))
+        );
+        assert_eq!(
+            Selector::parse("div.."),
+            Err(CssParseError::ExpectedIdentifier(4))
+        );
+        // trailing garbage after the selector
+        assert!(Selector::parse("div {").is_err());
+        assert!(Selector::parse("p, h1").is_err());
+    }
+
+    #[test]
+    fn test_skip_at_rules() {
+        let css = r#"
+@import url("theme.css");
+@font-face { font-family: "X"; src: url(x.woff) }
+@keyframes spin { from { opacity: 0 } to { opacity: 1 } }
+p { color: red }
+@page { margin: 1in }
+div { margin: 0 }
+"#;
+        let mut parser = StyleSheetParser::new(css);
+        let stylesheet = parser.parse().unwrap();
+        assert_eq!(
+            stylesheet
+                .rules()
+                .iter()
+                .map(|rule| rule.selectors().to_vec())
+                .collect::<Vec<_>>(),
+            vec![
+                vec![Selector::parse("p").unwrap()],
+                vec![Selector::parse("div").unwrap()]
+            ]
+        );
+        assert_eq!(parser.errors(), &[]);
+    }
+
+    #[test]
+    fn test_color_hex() {
+        let tests = [
+            ("#c00", Color::new(0xcc, 0x00, 0x00, 0xff), "#cc0000ff"),
+            ("#c008", Color::new(0xcc, 0x00, 0x00, 0x88), "#cc000088"),
+            ("#CC0000", Color::new(0xcc, 0x00, 0x00, 0xff), "#cc0000ff"),
+            ("#cc000080", Color::new(0xcc, 0x00, 0x00, 0x80), "#cc000080"),
+        ];
+        for (hex, color, normalized) in tests {
+            assert_eq!(Color::from_hex(hex), Some(color.clone()));
+            assert_eq!(color.to_hex(), normalized);
+            assert_eq!(Color::from_hex(&color.to_hex()), Some(color));
+        }
+        assert_eq!(Color::from_hex("#12"), None);
+        assert_eq!(Color::from_hex("#ggg"), None);
+    }
+
+    #[test]
+    fn test_unbalanced_braces() {
+        assert_eq!(
+            StyleSheetParser::new("p { margin: 0 } div { color: red").parse(),
+            Err(CssParseError::UnterminatedRule("div".to_string()))
+        );
+        assert_eq!(
+            StyleSheetParser::new("p { margin: 0 } div ").parse(),
+            Err(CssParseError::UnterminatedSelector("div".to_string()))
+        );
+        assert_eq!(
+            CssParseError::UnterminatedRule("div".to_string()).to_string(),
+            "rule `div` is not closed by '}'"
+        );
+    }
+
+    #[test]
+    fn test_adjacent_and_general_sibling() {
+        use ElementTagName::*;
+        assert_eq!(
+            Selector::parse("h1 ~ p"),
+            Ok(Selector::GeneralSibling(
+                Box::new(Selector::Tag(H1)),
+                Box::new(Selector::Tag(P))
+            ))
+        );
+        assert_eq!(
+            Selector::parse("h1 + p"),
+            Ok(Selector::Adjacent(
+                Box::new(Selector::Tag(H1)),
+                Box::new(Selector::Tag(P))
+            ))
+        );
+        let page = render_html_with_css(
+            "<div><h1>a</h1><p>b</p><span>c</span><p>d</p></div>",
+            "h1 + p { margin-left: 1px } h1 ~ p { margin-top: 2px }",
+        )
+        .unwrap();
+        let margins = page
+            .render_object
+            .children
+            .iter()
+            .map(|object| {
+                (
+                    object.get_length(&DeclarationProperty::MarginLeft),
+                    object.get_length(&DeclarationProperty::MarginTop),
+                )
+            })
+            .collect::<Vec<_>>();
+        // only the immediate p is adjacent, but every following p is a general sibling
+        assert_eq!(
+            margins,
+            vec![(0.0, 0.0), (1.0, 2.0), (0.0, 0.0), (0.0, 2.0)]
+        );
+    }
+
+    #[test]
+    fn test_rgb_percentage_and_space() {
+        let mut parser = StyleSheetParser::new(
+            "color: rgb(100% 0% 0%); background-color: rgb(255 0 0 / 0.5); color: rgb(0, 128, 255)",
+        );
+        assert_eq!(
+            parser
+                .parse_inline()
+                .into_iter()
+                .map(|declaration| declaration.value)
+                .collect::<Vec<_>>(),
+            vec![
+                DeclarationValue::Color(Color::new(0xff, 0x00, 0x00, 0xff)),
+                DeclarationValue::Color(Color::new(0xff, 0x00, 0x00, 0x80)),
+                DeclarationValue::Color(Color::new(0x00, 0x80, 0xff, 0xff)),
+            ]
+        );
+        assert_eq!(parser.errors(), &[]);
+    }
+
+    #[test]
+    fn test_not_pseudo_class() {
+        use ElementTagName::*;
+        assert_eq!(
+            Selector::parse("p:not(.special)"),
+            Ok(Selector::Pseudo(
+                Some(Box::new(Selector::Tag(P))),
+                PseudoClass::Not(Box::new(Selector::Class(None, "special".to_string())))
+            ))
+        );
+        let special = Element::new(
+            P,
+            ElementAttributes::from_iter([(NodeKey::Class, "special".to_string())]),
+            vec![],
+        );
+        let plain = Element::new(P, ElementAttributes::new(), vec![]);
+        let div = Element::new(Div, ElementAttributes::new(), vec![]);
+        let identified = Element::new(
+            Div,
+            ElementAttributes::from_iter([(NodeKey::Id, "main".to_string())]),
+            vec![],
+        );
+        let selector = Selector::parse("p:not(.special)").unwrap();
+        assert!(!selector.matches(&special));
+        assert!(selector.matches(&plain));
+        assert!(!selector.matches(&div));
+        assert!(!Selector::parse(":not(div)").unwrap().matches(&div));
+        assert!(Selector::parse(":not(div)").unwrap().matches(&plain));
+        assert!(!Selector::parse("div:not(#main)")
+            .unwrap()
+            .matches(&identified));
+        assert!(Selector::parse("div:not(#main)").unwrap().matches(&div));
+    }
+
+    #[test]
+    fn test_first_and_last_child() {
+        let page = render_html_with_css(
+            "<ul><li>a</li><li>b</li><li>c</li></ul>",
+            "li:first-child { margin-left: 1px } li:last-child { margin-top: 2px }",
+        )
+        .unwrap();
+        let margins = page
+            .render_object
+            .children
+            .iter()
+            .map(|object| {
+                (
+                    object.get_length(&DeclarationProperty::MarginLeft),
+                    object.get_length(&DeclarationProperty::MarginTop),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(margins, vec![(1.0, 0.0), (0.0, 0.0), (0.0, 2.0)]);
+    }
+
+    #[test]
+    fn test_import_fetcher() {
+        let css = r#"@import url("theme.css"); @import "missing.css"; p { color: red }"#;
+        let mut requested = vec![];
+        let stylesheet = StyleSheetParser::new(css)
+            .with_import_fetcher(|url| {
+                requested.push(url.to_string());
+                match url {
+                    "theme.css" => Some("h1 { margin: 0 } p { color: blue }".to_string()),
+                    _ => None,
+                }
+            })
+            .parse()
+            .unwrap();
+        // imported rules come first, so the importing sheet wins ties
+        assert_eq!(
+            stylesheet.to_string(),
+            StyleSheetParser::new("h1 { margin: 0 } p { color: blue } p { color: red }")
+                .parse()
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(requested, vec!["theme.css", "missing.css"]);
+
+        // imports are ignored without a fetcher
+        let stylesheet = StyleSheetParser::new(css).parse().unwrap();
+        assert_eq!(stylesheet.rules().len(), 1);
+    }
+
+    #[test]
+    fn test_css_string() {
+        let mut parser = StyleSheetParser::new(
+            r#"content: "hello, world"; font-family: 'Times New Roman', serif; content: "say \"hi\"""#,
+        );
+        let declarations = parser.parse_inline();
+        assert_eq!(
+            declarations[0].value,
+            DeclarationValue::String("hello, world".to_string())
+        );
+        assert_eq!(
+            declarations[2].value,
+            DeclarationValue::String(r#"say "hi""#.to_string())
+        );
+        assert_eq!(
+            declarations
+                .iter()
+                .map(|declaration| declaration.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                r#"content: "hello, world""#,
+                r#"font-family: "Times New Roman", serif"#,
+                r#"content: "say \"hi\"""#,
+            ]
+        );
+        assert_eq!(parser.errors(), &[]);
+    }
+
+    #[test]
+    fn test_duplicated_declaration() {
+        let stylesheet = StyleSheetParser::new(
+            "p { color: red; margin-left: 1px !important; color: blue; margin-left: 2px }",
+        )
+        .parse()
+        .unwrap();
+        let rule = &stylesheet.rules()[0];
+        assert_eq!(
+            rule.get_styles(false).get(&DeclarationProperty::Color),
+            Some(&DeclarationValue::Color(Color::new(0x00, 0x00, 0xff, 0xff)))
+        );
+        // !important in the same rule wins over the later normal declaration
+        let page = render_html_with_css("<div><p>a</p></div>", &stylesheet.to_string()).unwrap();
+        assert_eq!(
+            page.render_object.children[0].get_length(&DeclarationProperty::MarginLeft),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_strict_unknown_property() {
+        let css = "p { colr: red; color: blue; --main: 1px; -webkit-foo: 0 }";
+        let mut parser = StyleSheetParser::new(css).with_strict(true);
+        let stylesheet = parser.parse().unwrap();
+        // the value is still parsed and kept
+        assert_eq!(stylesheet.rules()[0].declarations().len(), 4);
+        assert_eq!(
+            parser.warnings(),
+            &[CssParseWarning::UnknownProperty("colr".to_string(), 4)]
+        );
+        assert_eq!(parser.errors(), &[]);
+
+        let mut parser = StyleSheetParser::new(css);
+        parser.parse().unwrap();
+        assert_eq!(parser.warnings(), &[]);
+    }
+
+    #[test]
+    fn test_rules_matching() {
+        let stylesheet = StyleSheetParser::new(
+            "p { color: red } div { margin: 0 } .note { color: blue } h1, p.note { opacity: 0.5 }",
+        )
+        .parse()
+        .unwrap();
+        let element = Element::new(
+            ElementTagName::P,
+            ElementAttributes::from_iter([(NodeKey::Class, "note".to_string())]),
+            vec![],
+        );
+        let rules = stylesheet.rules();
+        assert_eq!(
+            stylesheet.rules_matching(&element),
+            vec![&rules[0], &rules[2], &rules[3]]
+        );
+    }
+
+    #[test]
+    fn test_charset() {
+        let stylesheet = StyleSheetParser::new(r#"@charset "utf-8"; p { color: red }"#)
+            .parse()
+            .unwrap();
+        assert_eq!(stylesheet.charset(), Some("utf-8".to_string()));
+        assert_eq!(stylesheet.rules().len(), 1);
+        // only a leading @charset is recorded
+        let stylesheet = StyleSheetParser::new(r#"p { color: red } @charset "utf-8";"#)
+            .parse()
+            .unwrap();
+        assert_eq!(stylesheet.charset(), None);
+        assert_eq!(stylesheet.rules().len(), 1);
+    }
+
+    #[test]
+    fn test_selector_order() {
+        let mut selectors: Vec<Selector> = ["p", "#id", ".class"]
+            .iter()
+            .map(|s| Selector::parse(s).unwrap())
+            .collect();
+        selectors.sort_by(|a, b| b.cmp(a));
+        let sorted: Vec<String> = selectors.iter().map(|s| s.to_string()).collect();
+        assert_eq!(sorted, vec!["#id", ".class", "p"]);
+    }
+
+    #[test]
+    fn test_selector_order_consistent_with_eq() {
+        use std::cmp::Ordering;
+        use ElementTagName::*;
+        let tag = Selector::Tag;
+        let descendant = |a, b| Selector::Descendant(Box::new(a), Box::new(b));
+        let selectors = [
+            Selector::parse("#a").unwrap(),
+            Selector::parse("#b").unwrap(),
+            // printed as #a by Debug
+            Selector::Pseudo(None, PseudoClass::Other("a".to_string())),
+            Selector::parse("div p").unwrap(),
+            Selector::parse("div > p").unwrap(),
+            Selector::parse("div + p").unwrap(),
+            // same textual form "a p div", but grouped differently
+            descendant(tag(A), descendant(tag(P), tag(Div))),
+            descendant(descendant(tag(A), tag(P)), tag(Div)),
+        ];
+        for a in selectors.iter() {
+            for b in selectors.iter() {
+                assert_eq!(a.cmp(b) == Ordering::Equal, a == b, "{} and {}", a, b);
+                assert_eq!(a.cmp(b), b.cmp(a).reverse());
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_height() {
+        let mut parser =
+            StyleSheetParser::new("line-height: 24px; line-height: 1.5; line-height: normal");
+        assert_eq!(
+            parser
+                .parse_inline()
+                .into_iter()
+                .map(|declaration| declaration.value)
+                .collect::<Vec<_>>(),
+            vec![
+                DeclarationValue::LineHeight(LineHeight::Length(Length::Actual(24.0, Unit::Px))),
+                DeclarationValue::LineHeight(LineHeight::Number(1.5)),
+                DeclarationValue::LineHeight(LineHeight::Normal),
+            ]
+        );
+        assert_eq!(parser.errors(), &[]);
+    }
+
+    #[test]
+    fn test_font_weight() {
+        let mut parser = StyleSheetParser::new(
+            "font-weight: bold; font-weight: 400; font-weight: 700; font-weight: normal",
+        );
+        assert_eq!(
+            parser
+                .parse_inline()
+                .into_iter()
+                .map(|declaration| declaration.value)
+                .collect::<Vec<_>>(),
+            vec![
+                DeclarationValue::FontWeight(700),
+                DeclarationValue::FontWeight(400),
+                DeclarationValue::FontWeight(700),
+                DeclarationValue::FontWeight(400),
+            ]
+        );
+        assert_eq!(parser.errors(), &[]);
+    }
+
+    #[test]
+    fn test_text_align() {
+        let mut parser = StyleSheetParser::new(
+            "text-align: left; text-align: center; text-align: right; text-align: justify; \
+             text-align: middle",
+        );
+        assert_eq!(
+            parser
+                .parse_inline()
+                .into_iter()
+                .map(|declaration| declaration.value)
+                .collect::<Vec<_>>(),
+            vec![
+                DeclarationValue::TextAlign(TextAlign::Left),
+                DeclarationValue::TextAlign(TextAlign::Center),
+                DeclarationValue::TextAlign(TextAlign::Right),
+                DeclarationValue::TextAlign(TextAlign::Justify),
+                // unknown keyword is left
+                DeclarationValue::TextAlign(TextAlign::Left),
+            ]
+        );
+        assert_eq!(parser.errors(), &[]);
+    }
+
+    #[test]
+    fn test_display_from() {
+        assert_eq!(Display::from("inline"), Display::Inline);
+        assert_eq!(Display::from("block"), Display::Block);
+        assert_eq!(Display::from("flex"), Display::Flex);
+        // unknown keyword is the initial value
+        assert_eq!(Display::from("contents"), Display::Inline);
+    }
+
+    #[test]
+    fn test_grouped_combinator_selectors() {
+        use ElementTagName::*;
+        let stylesheet = StyleSheetParser::new("div > p, h1 + span { color: red }")
+            .parse()
+            .unwrap();
+        assert_eq!(
+            stylesheet.rules()[0].selectors(),
+            &[
+                Selector::Child(Box::new(Selector::Tag(Div)), Box::new(Selector::Tag(P))),
+                Selector::Adjacent(Box::new(Selector::Tag(H1)), Box::new(Selector::Tag(Span))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_opacity() {
+        let mut parser = StyleSheetParser::new(
+            "opacity: 0.5; opacity: 1.5; opacity: -0.2; opacity: 50%; opacity: .25",
+        );
+        assert_eq!(
+            parser
+                .parse_inline()
+                .into_iter()
+                .map(|declaration| declaration.value)
+                .collect::<Vec<_>>(),
+            vec![
+                DeclarationValue::Number(0.5),
+                // out of range values are clamped to [0, 1]
+                DeclarationValue::Number(1.0),
+                DeclarationValue::Number(0.0),
+                DeclarationValue::Number(0.5),
+                DeclarationValue::Number(0.25),
+            ]
+        );
+        assert_eq!(parser.errors(), &[]);
+    }
+
+    #[test]
+    fn test_keyword_properties() {
+        let values = |css: &str| {
+            StyleSheetParser::new(css)
+                .parse_inline()
+                .into_iter()
+                .map(|declaration| declaration.value)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values(
+                "overflow: visible; overflow: hidden; overflow: clip; overflow: scroll; \
+                 overflow: auto; overflow: wavy"
+            ),
+            vec![
+                DeclarationValue::Overflow(Overflow::Visible),
+                DeclarationValue::Overflow(Overflow::Hidden),
+                DeclarationValue::Overflow(Overflow::Hidden),
+                DeclarationValue::Overflow(Overflow::Scroll),
+                DeclarationValue::Overflow(Overflow::Auto),
+                DeclarationValue::Overflow(Overflow::Visible),
+            ]
+        );
+        assert_eq!(
+            values("visibility: visible; visibility: hidden; visibility: collapse; visibility: x"),
+            vec![
+                DeclarationValue::Visibility(Visibility::Visible),
+                DeclarationValue::Visibility(Visibility::Hidden),
+                DeclarationValue::Visibility(Visibility::Collapse),
+                DeclarationValue::Visibility(Visibility::Visible),
+            ]
+        );
+        assert_eq!(
+            values(
+                "position: static; position: relative; position: absolute; position: fixed; \
+                 position: sticky; position: x"
+            ),
+            vec![
+                DeclarationValue::Position(Position::Static),
+                DeclarationValue::Position(Position::Relative),
+                DeclarationValue::Position(Position::Absolute),
+                DeclarationValue::Position(Position::Fixed),
+                DeclarationValue::Position(Position::Sticky),
+                DeclarationValue::Position(Position::Static),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_vendor_prefixed_property() {
+        let mut parser = StyleSheetParser::new(
+            "-webkit-transform: translate(10px, 20px); -moz-box-shadow: 0 0 2px rgba(0, 0, 0, 0.5)",
+        );
+        assert_eq!(
+            parser.parse_inline(),
+            vec![
+                Declaration::new(
+                    DeclarationProperty::Other("-webkit-transform".to_string()),
+                    DeclarationValue::Other("translate(10px, 20px)".to_string())
+                ),
+                Declaration::new(
+                    DeclarationProperty::Other("-moz-box-shadow".to_string()),
+                    DeclarationValue::Other("0 0 2px rgba(0, 0, 0, 0.5)".to_string())
+                ),
+            ]
+        );
+        assert_eq!(parser.errors(), &[]);
+    }
+
+    #[test]
+    fn test_multi_token_unknown_property() {
+        let stylesheet = StyleSheetParser::new(
+            "p { transition: all 0.3s ease; color: red; will-change: opacity, transform; margin-left: 1px }",
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(
+            stylesheet.rules()[0]
+                .declarations()
+                .iter()
+                .map(|declaration| declaration.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "transition: all 0.3s ease",
+                "color: #ff0000ff",
+                "will-change: opacity, transform",
+                "margin-left: 1px",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_stylesheets() {
+        use super::super::user_agent_stylesheet;
+        let user_agent = user_agent_stylesheet();
+        let author = StyleSheetParser::new("h1 { font-size: 10px }")
+            .parse()
+            .unwrap();
+        let mut merged = user_agent_stylesheet();
+        merged.merge(author.clone());
+        let count = user_agent.rules().len();
+        assert_eq!(merged.rules().len(), count + 1);
+        assert_eq!(merged.rules()[..count], user_agent.rules()[..]);
+        assert_eq!(merged.rules()[count], author.rules()[0]);
+        // the later author rule wins over `h1 { font-size: 2em }` of the user agent
+        let h1 = Element::new(ElementTagName::H1, ElementAttributes::new(), vec![]);
+        assert_eq!(
+            merged
+                .get_styles(&MatchContext::new(&h1))
+                .get(&DeclarationProperty::FontSize),
+            Some(&DeclarationValue::Length(Length::Actual(10.0, Unit::Px)))
+        );
+    }
+
+    #[test]
+    fn test_flex_direction_and_justify_content() {
+        let values = |css: &str| {
+            StyleSheetParser::new(css)
+                .parse_inline()
+                .into_iter()
+                .map(|declaration| declaration.value)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values("flex-direction: row; flex-direction: column; flex-direction: x"),
+            vec![
+                DeclarationValue::FlexDirection(FlexDirection::Row),
+                DeclarationValue::FlexDirection(FlexDirection::Column),
+                DeclarationValue::FlexDirection(FlexDirection::Row),
+            ]
+        );
+        assert_eq!(
+            values(
+                "justify-content: flex-start; justify-content: center; \
+                 justify-content: space-between; justify-content: x"
+            ),
+            vec![
+                DeclarationValue::JustifyContent(JustifyContent::FlexStart),
+                DeclarationValue::JustifyContent(JustifyContent::Center),
+                DeclarationValue::JustifyContent(JustifyContent::SpaceBetween),
+                DeclarationValue::JustifyContent(JustifyContent::FlexStart),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_style_map_order() {
+        let declarations = [
+            (DeclarationProperty::Color, "color: red"),
+            (DeclarationProperty::MarginLeft, "margin-left: 1px"),
+            (DeclarationProperty::Display, "display: block"),
+            (DeclarationProperty::Opacity, "opacity: 0.5"),
+        ];
+        let style_map = |order: &[usize]| {
+            order
+                .iter()
+                .map(|&i| {
+                    let declaration = &StyleSheetParser::new(declarations[i].1).parse_inline()[0];
+                    (declarations[i].0.clone(), declaration.value.clone())
+                })
+                .collect::<StyleMap>()
+        };
+        // the debug output doesn't depend on the insertion order
+        assert_eq!(
+            format!("{:?}", style_map(&[0, 1, 2, 3])),
+            format!("{:?}", style_map(&[3, 1, 0, 2]))
+        );
+        assert_eq!(
+            format!("{:?}", style_map(&[2, 0, 3, 1])),
+            format!("{:?}", style_map(&[1, 3, 2, 0]))
+        );
+    }
+
+    #[test]
+    fn test_other_property_sorts_last() {
+        let mut properties = vec![
+            DeclarationProperty::Other("-webkit-transform".to_string()),
+            DeclarationProperty::Color,
+            DeclarationProperty::Other("cursor".to_string()),
+            DeclarationProperty::Margin,
+            DeclarationProperty::Opacity,
+        ];
+        properties.sort();
+        assert_eq!(
+            properties,
+            vec![
+                DeclarationProperty::Margin,
+                DeclarationProperty::Color,
+                DeclarationProperty::Opacity,
+                DeclarationProperty::Other("-webkit-transform".to_string()),
+                DeclarationProperty::Other("cursor".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cascade_tiers() {
+        let color = |css: &str, style: &str| {
+            let engine = StyleEngine::new(StyleSheetParser::new(css).parse().unwrap());
+            let element = Element::new(
+                ElementTagName::P,
+                ElementAttributes::from_iter([(NodeKey::Style, style.to_string())]),
+                vec![],
+            );
+            engine
+                .get_styles(&MatchContext::new(&element))
+                .get(&DeclarationProperty::Color)
+                .cloned()
+        };
+        let red = Some(DeclarationValue::Color(Color::new(0xff, 0x00, 0x00, 0xff)));
+        let blue = Some(DeclarationValue::Color(Color::new(0x00, 0x00, 0xff, 0xff)));
+        // without inline style, the author's rule applies
+        assert_eq!(color("p { color: red }", ""), red);
+        // inline style wins over the author's normal declaration
+        assert_eq!(color("p { color: red }", "color: blue"), blue);
+        // the author's !important wins over inline style
+        assert_eq!(color("p { color: red !important }", "color: blue"), red);
+        // inline !important wins over the author's !important
+        assert_eq!(
+            color("p { color: red !important }", "color: blue !important"),
+            blue
+        );
+    }
+
+    #[test]
+    fn test_author_wins_over_user_agent() {
+        let engine = StyleEngine::new(
+            StyleSheetParser::new("h1 { font-size: 10px } a { color: red }")
+                .parse()
+                .unwrap(),
+        );
+        let h1 = Element::new(ElementTagName::H1, ElementAttributes::new(), vec![]);
+        let styles = engine.get_styles(&MatchContext::new(&h1));
+        assert_eq!(
+            styles.get(&DeclarationProperty::FontSize),
+            Some(&DeclarationValue::Length(Length::Actual(10.0, Unit::Px)))
+        );
+        // font-weight: bold of the user agent still applies
+        assert_eq!(
+            styles.get(&DeclarationProperty::FontWeight),
+            Some(&DeclarationValue::FontWeight(700))
+        );
+        let a = Element::new(ElementTagName::A, ElementAttributes::new(), vec![]);
+        assert_eq!(
+            engine
+                .get_styles(&MatchContext::new(&a))
+                .get(&DeclarationProperty::Color),
+            Some(&DeclarationValue::Color(Color::new(0xff, 0x00, 0x00, 0xff)))
+        );
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        assert_eq!(
+            StyleSheetParser::new("\u{feff}p { color: red }").parse(),
+            StyleSheetParser::new("p { color: red }").parse()
+        );
+    }
+
+    #[test]
+    fn test_font_shorthand() {
+        let strings = |css: &str| {
+            StyleSheetParser::new(css)
+                .parse_inline()
+                .iter()
+                .map(|declaration| declaration.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            strings("font: bold 16px/1.5 Arial, sans-serif"),
+            vec![
+                "font-weight: 700",
+                "font-size: 16px",
+                "line-height: 1.5",
+                "font-family: Arial, sans-serif",
+            ]
+        );
+        // omitted font-weight and line-height are reset
+        assert_eq!(
+            strings("font: 16px Arial"),
+            vec![
+                "font-weight: 400",
+                "font-size: 16px",
+                "line-height: normal",
+                "font-family: Arial",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grid() {
+        let mut parser =
+            StyleSheetParser::new("display: grid; grid-template-columns: 100px 1fr auto");
+        assert_eq!(
+            parser.parse_inline(),
+            vec![
+                Declaration::new(
+                    DeclarationProperty::Display,
+                    DeclarationValue::Display(Display::Grid)
+                ),
+                Declaration::new(
+                    DeclarationProperty::GridTemplateColumns,
+                    DeclarationValue::TrackList(vec![
+                        TrackSize::Length(Length::Actual(100.0, Unit::Px)),
+                        TrackSize::Fr(1.0),
+                        TrackSize::Length(Length::Auto),
+                    ])
+                ),
+            ]
+        );
+        assert_eq!(parser.errors(), &[]);
+    }
+
+    #[test]
+    fn test_border_radius_shorthand() {
+        let strings = |css: &str| {
+            StyleSheetParser::new(css)
+                .parse_inline()
+                .iter()
+                .map(|declaration| declaration.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            strings("border-radius: 4px"),
+            vec![
+                "border-top-left-radius: 4px",
+                "border-top-right-radius: 4px",
+                "border-bottom-right-radius: 4px",
+                "border-bottom-left-radius: 4px",
+            ]
+        );
+        assert_eq!(
+            strings("border-radius: 4px 8px"),
+            vec![
+                "border-top-left-radius: 4px",
+                "border-top-right-radius: 8px",
+                "border-bottom-right-radius: 4px",
+                "border-bottom-left-radius: 8px",
+            ]
+        );
+        assert_eq!(
+            strings("border-radius: 1px 2px 3px 4px"),
+            vec![
+                "border-top-left-radius: 1px",
+                "border-top-right-radius: 2px",
+                "border-bottom-right-radius: 3px",
+                "border-bottom-left-radius: 4px",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_margin_mixed_auto() {
+        let strings = |css: &str| {
+            StyleSheetParser::new(css)
+                .parse_inline()
+                .iter()
+                .map(|declaration| declaration.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            strings("margin: 10px auto"),
+            vec![
+                "margin-top: 10px",
+                "margin-right: auto",
+                "margin-bottom: 10px",
+                "margin-left: auto",
+            ]
+        );
+        assert_eq!(
+            strings("margin: -5px auto 10px"),
+            vec![
+                "margin-top: -5px",
+                "margin-right: auto",
+                "margin-bottom: 10px",
+                "margin-left: auto",
+            ]
+        );
+        assert_eq!(
+            strings("padding: auto -1.5em 0 2px"),
+            vec![
+                "padding-top: auto",
+                "padding-right: -1.5em",
+                "padding-bottom: 0px",
+                "padding-left: 2px",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transition_and_animation() {
+        let declarations = StyleSheetParser::new(
+            "transition: all 0.2s ease-in-out; animation: spin 1s linear infinite",
+        )
+        .parse_inline();
+        assert_eq!(
+            declarations,
+            vec![
+                Declaration::new(
+                    DeclarationProperty::Transition,
+                    DeclarationValue::Other("all 0.2s ease-in-out".to_string())
+                ),
+                Declaration::new(
+                    DeclarationProperty::Animation,
+                    DeclarationValue::Other("spin 1s linear infinite".to_string())
+                ),
+            ]
+        );
+        assert_eq!(
+            declarations[0].to_string(),
+            "transition: all 0.2s ease-in-out"
+        );
+        assert_eq!(
+            StyleSheetParser::new(&declarations[1].to_string()).parse_inline(),
+            vec![declarations[1].clone()]
+        );
+    }
+}
@@ -1,5 +1,3 @@
-use std::iter::FromIterator;
-
 use itertools::Itertools;
 
 use super::*;
@@ -8,9 +6,13 @@ pub mod prelude;
 mod test;
 
 impl<'a> DocumentObjectParser<'a> {
+    /// A leading byte order mark is ignored
     pub fn new(input: &'a str) -> Self {
+        let input = input.strip_prefix('\u{feff}').unwrap_or(input);
         Self {
             input: input.chars().peekable(),
+            len: input.chars().count(),
+            preformatted: false,
         }
     }
 
@@ -33,125 +35,240 @@ impl<'a> DocumentObjectParser<'a> {
     ///   </body>
     /// </html>
     /// "#;
-    /// let mut dom = DocumentObjectParser::new(html).parse();
+    /// let mut dom = DocumentObjectParser::new(html).parse().unwrap();
     /// ```
-    pub fn parse(&mut self) -> Node {
-        self.skip_doctype(); //TODO: <!doctype html>をskipしてる
-        self.parse_node()
+    pub fn parse(&mut self) -> Result<Node, HtmlParseError> {
+        // leading <!doctype html>, comments and text are skipped
+        self.parse_document()?
+            .into_iter()
+            .find(|node| matches!(node, Node::Element(_)))
+            .ok_or(HtmlParseError::NoRootElement)
+    }
+
+    /// Parse raw HTML input to top-level nodes, e.g. doctype, comments and sibling root elements
+    ///
+    /// e.g.
+    ///   <!doctype html><!-- a --><div/><div/>
+    ///     → [Node::Doctype, Node::Comment, Node::Element, Node::Element]
+    pub fn parse_document(&mut self) -> Result<Vec<Node>, HtmlParseError> {
+        let mut nodes = vec![];
+        while self.peek().is_some() {
+            match self.parse_node()? {
+                Node::EndTag => {} // ignore stray end tag
+                node => nodes.push(node),
+            }
+        }
+        Ok(nodes)
     }
 
-    fn parse_node(&mut self) -> Node {
-        match self.peek() {
+    /// Parse HTML fragment, which has no root element, e.g. for templates
+    ///
+    /// e.g.
+    ///   Hello <b>world</b>! → [Node::Text("Hello"), Node::Element(b), Node::Text("!")]
+    pub fn parse_fragment(&mut self) -> Result<Vec<Node>, HtmlParseError> {
+        self.parse_document()
+    }
+
+    fn parse_node(&mut self) -> Result<Node, HtmlParseError> {
+        // whitespace is a part of text in <pre>
+        let next = match self.preformatted {
+            true => self.input.peek().copied(),
+            false => self.peek().copied(),
+        };
+        let node = match next {
             Some('<') => {
                 self.bump();
+                if self.starts_with("!doctype") {
+                    return Ok(Node::Doctype(self.parse_doctype()));
+                }
                 match self.peek() {
                     Some('!') => Node::Comment(self.parse_comment()),
-                    Some('a'..='z' | 'A'..='Z') => Node::Element(self.parse_element()),
+                    Some('a'..='z' | 'A'..='Z') => Node::Element(self.parse_element()?),
                     Some('/') => {
-                        self.skip_next_end_tag();
+                        self.skip_next_end_tag()?;
                         Node::EndTag
                     }
+                    Some('>') => return Err(HtmlParseError::EmptyTag(self.position())),
                     _ => panic!("Cannot parse node"),
                 }
             }
+            Some(_) if self.preformatted => Node::Text(self.consume_preformatted_text()),
             Some(_) => Node::Text(self.consume_text()),
-            None => panic!("Cannot parse node"),
-        }
+            // e.g. unterminated element <div>a
+            None => return Err(HtmlParseError::UnexpectedEof),
+        };
+        Ok(node)
     }
 
-    fn parse_element(&mut self) -> Element {
+    fn parse_element(&mut self) -> Result<Element, HtmlParseError> {
         let tag_name = self.parse_element_tag();
         let attributes = match self.peek() {
             Some('/' | '>') => ElementAttributes::new(),
-            Some('a'..='z' | 'A'..='Z') => self.parse_element_attributes(),
+            Some('a'..='z' | 'A'..='Z') => self.parse_element_attributes()?,
+            Some('<') => return Err(self.unexpected_tag_open()),
             _ => panic!("Cannot parse element"),
         };
         let children = match self.peek() {
+            // self-closing tag, e.g. <br/>, <br />, <br / >
             Some('/') => {
-                self.skip_next_str("/>");
+                self.skip_next_ch(&'/');
+                self.skip_next_ch(&'>');
+                vec![]
+            }
+            Some('>') if tag_name.is_void() => {
+                self.skip_next_ch(&'>');
                 vec![]
             }
             Some('>') => {
                 self.skip_next_ch(&'>');
-                if tag_name == ElementTagName::Style {
+                match tag_name {
                     // TODO: find better practice
-                    self.skip_style()
-                } else {
-                    self.parse_children()
+                    ElementTagName::Style => self.skip_style()?,
+                    ElementTagName::Pre | ElementTagName::Textarea => {
+                        self.parse_preformatted_children()?
+                    }
+                    _ => self.parse_children()?,
                 }
             }
             _ => panic!("Cannot parse element"),
         };
-        Element::new(tag_name, attributes, children)
+        Ok(Element::new(tag_name, attributes, children))
     }
 
     // TODO: find better practice
-    fn skip_style(&mut self) -> Vec<Node> {
-        let style = vec![Node::Style(self.consume(&|ch| !matches!(ch, '<' | '>')))];
-        self.parse_node();
-        style
+    fn skip_style(&mut self) -> Result<Vec<Node>, HtmlParseError> {
+        // `>` of child combinators is a part of style, e.g. div > p
+        let style = vec![Node::Style(self.consume(&|ch| !matches!(ch, '<')))];
+        self.parse_node()?;
+        Ok(style)
     }
 
-    fn parse_element_attributes(&mut self) -> ElementAttributes {
-        let mut attributes = vec![];
+    /// Parse attributes of the start tag. The first one wins when a key is repeated as in HTML,
+    /// e.g. class is "a" for <div class="a" class="b">
+    fn parse_element_attributes(&mut self) -> Result<ElementAttributes, HtmlParseError> {
+        let mut attributes = ElementAttributes::new();
         loop {
             match self.peek() {
                 Some('/' | '>') => break,
+                Some('<') => return Err(self.unexpected_tag_open()),
                 Some(_) => {
+                    // whitespace around `=` is allowed, e.g. <div class = "name">
                     let attribute_key = NodeKey::from(self.consume_identifier().as_ref());
                     self.skip_next_ch(&'=');
                     let attribute_value = self.consume_string();
-                    attributes.push((attribute_key, attribute_value));
+                    attributes.entry(attribute_key).or_insert(attribute_value);
                 }
                 _ => panic!("cannot parse element attributes"),
             }
         }
-        ElementAttributes::from_iter(attributes)
+        Ok(attributes)
     }
 
-    fn parse_children(&mut self) -> Vec<Node> {
+    /// `<` in a start tag, e.g. a comment inside <div <!-- x --> >
+    fn unexpected_tag_open(&mut self) -> HtmlParseError {
+        HtmlParseError::UnexpectedChar('<', self.position())
+    }
+
+    fn parse_children(&mut self) -> Result<Vec<Node>, HtmlParseError> {
         let mut children = vec![];
         loop {
-            let node = self.parse_node();
+            let node = self.parse_node()?;
             match node {
-                Node::EndTag => return children,
+                Node::EndTag => return Ok(children),
                 _ => children.push(node),
             };
         }
     }
 
+    /// Parse children of <pre>, <textarea> keeping whitespace and newlines of text as is,
+    /// including text of descendants, e.g. <pre><b>  a  </b></pre>
+    fn parse_preformatted_children(&mut self) -> Result<Vec<Node>, HtmlParseError> {
+        // a newline immediately following the start tag is ignored
+        self.input.next_if_eq(&'\n');
+        let outer = std::mem::replace(&mut self.preformatted, true);
+        let children = self.parse_children();
+        self.preformatted = outer;
+        children
+    }
+
     fn parse_element_tag(&mut self) -> ElementTagName {
-        let tag_name = self.consume_identifier();
+        let tag_name = self.consume_tag_name();
         ElementTagName::from(tag_name.as_ref())
     }
 
-    fn skip_doctype(&mut self) {
-        self.skip_next_str("<!doctype html>");
+    /// Parse `!doctype html>` to `html`. The keyword is case-insensitive as `<!DOCTYPE html>`
+    fn parse_doctype(&mut self) -> String {
+        for _ in "!doctype".chars() {
+            self.bump();
+        }
+        let doctype = self.consume(&|ch| *ch != '>').trim().to_string();
+        self.skip_next_ch(&'>');
+        doctype
     }
 
+    /// Read comment to `-->` trimming whitespace around it. Dashes can be in it
+    ///   e.g. <!-- a - b --> → `a - b`, <!--- a ---> → `- a -`
     fn parse_comment(&mut self) -> String {
         self.skip_next_str("!--");
-        let comment = self.consume(&|ch| !matches!(ch, '-'));
-        self.skip_next_str("-->");
-        comment
+        let mut comment = String::new();
+        for ch in self.input.by_ref() {
+            comment.push(ch);
+            if let Some(stripped) = comment.strip_suffix("-->") {
+                return stripped.trim().to_string();
+            }
+        }
+        // unterminated comment continues to the end of input
+        comment.trim().to_string()
     }
 
+    /// Read text collapsing runs of whitespace to a space and trimming both ends,
+    /// and entities in it are decoded. <pre> doesn't use this to keep whitespace.
+    /// Only `<` ends text, and `>` in it is literal
+    ///   e.g. `  a   b  ` → `a b`, a &lt; b → a < b, a > b → a > b
     fn consume_text(&mut self) -> String {
-        self.consume(&|ch| !matches!(ch, '<' | '>'))
-            .trim_end()
-            .to_owned()
-            .replace('\n', " ") // TODO: find better practice
+        let text = self
+            .consume(&|ch| !matches!(ch, '<'))
+            .split_ascii_whitespace()
+            .join(" ");
+        // decoded after collapsing, so &nbsp; is kept
+        decode_entities(&text)
+    }
+
+    fn consume_preformatted_text(&mut self) -> String {
+        let text = self
+            .input
+            .by_ref()
+            .peeking_take_while(|ch| !matches!(ch, '<'))
+            .join("");
+        decode_entities(&text)
     }
 
     fn consume_identifier(&mut self) -> String {
         self.consume(&|ch| matches!(ch, '0'..='9' | 'a'..='z' | 'A'..='Z' | '_' | '-'))
     }
 
+    /// Tag name can have a namespace prefix or hyphens
+    ///   e.g. svg:rect of <svg:rect>, my-widget of <my-widget>
+    fn consume_tag_name(&mut self) -> String {
+        self.consume(&|ch| matches!(ch, '0'..='9' | 'a'..='z' | 'A'..='Z' | '_' | '-' | ':'))
+    }
+
+    /// Read quoted attribute value, entities in it are decoded
+    ///   e.g. 'He said "hi"' → He said "hi"
+    ///        "x?a&amp;b"    → x?a&b
     fn consume_string(&mut self) -> String {
-        self.skip_next_ch(&'"');
-        let s = self.consume(&|ch| !matches!(ch, '"'));
-        self.skip_next_ch(&'"');
-        s
+        self.skip_whitespace();
+        let quote = match self.input.next() {
+            Some(ch @ ('"' | '\'')) => ch,
+            _ => panic!("Cannot found quote of attribute value"),
+        };
+        let s = self
+            .input
+            .by_ref()
+            .peeking_take_while(|ch| *ch != quote)
+            .join("");
+        self.skip_next_ch(&quote);
+        decode_entities(&s)
     }
 
     #[allow(dead_code)]
@@ -206,9 +323,13 @@ impl<'a> DocumentObjectParser<'a> {
 
     /// Skip eng tag
     ///   e.g. </div>
-    fn skip_next_end_tag(&mut self) {
-        self.consume(&|ch| !matches!(ch, '>'));
+    fn skip_next_end_tag(&mut self) -> Result<(), HtmlParseError> {
+        self.skip_next_ch(&'/');
+        if self.consume_tag_name().is_empty() {
+            return Err(HtmlParseError::EmptyEndTag(self.position()));
+        }
         self.skip_next_ch(&'>');
+        Ok(())
     }
 
     /// Skip specific next str
@@ -231,6 +352,17 @@ impl<'a> DocumentObjectParser<'a> {
         };
     }
 
+    /// Whether the next characters are `s` ignoring ASCII case (without consuming them),
+    /// so `s` must be lowercase
+    fn starts_with(&mut self, s: &'static str) -> bool {
+        self.skip_whitespace();
+        self.input
+            .clone()
+            .take(s.chars().count())
+            .map(|ch| ch.to_ascii_lowercase())
+            .eq(s.chars())
+    }
+
     fn skip_whitespace(&mut self) {
         while self.input.next_if(|&x| x.is_whitespace()).is_some() {}
     }
@@ -246,6 +378,285 @@ impl<'a> DocumentObjectParser<'a> {
         self.skip_whitespace();
         self.input.peek()
     }
+
+    /// Offset in characters of the next character from the start of input
+    fn position(&self) -> usize {
+        self.len - self.input.clone().count()
+    }
+}
+
+/// Escape text so that it is not read as markup, e.g. a < b & c → a &lt; b &amp; c
+fn escape_text(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Escape attribute value quoted by `"`, e.g. say "hi" → say &quot;hi&quot;
+fn escape_attribute(s: &str) -> String {
+    s.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+/// Resolve the URL of a link against the URL of the document
+///
+/// e.g. with base https://example.com/docs/a.html
+///   https://www.iana.org/ → https://www.iana.org/
+///   //cdn.example.com/x   → https://cdn.example.com/x
+///   /about                → https://example.com/about
+///   b.html                → https://example.com/docs/b.html
+///   ../c?q=1              → https://example.com/c?q=1
+///   #top                  → https://example.com/docs/a.html#top
+fn join_url(base: &str, href: &str) -> String {
+    let (scheme, rest) = match base.split_once("://") {
+        Some(split) if !has_scheme(href) => split,
+        // absolute URL, or base which cannot be joined
+        _ => return href.to_string(),
+    };
+    let host_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (host, base_path) = rest.split_at(host_end);
+    // path of base without query and fragment
+    let base_path = &base_path[..base_path.find(['?', '#']).unwrap_or(base_path.len())];
+    let base_path = if base_path.is_empty() { "/" } else { base_path };
+    let path = match href.chars().next() {
+        None => base_path.to_string(),
+        Some('/') if href.starts_with("//") => return format!("{}:{}", scheme, href),
+        Some('/') => href.to_string(),
+        Some('?') => format!("{}{}", base_path, href),
+        Some('#') => {
+            let base = &base[..base.find('#').unwrap_or(base.len())];
+            return format!("{}{}", base, href);
+        }
+        // relative to the directory of base
+        Some(_) => {
+            let dir = &base_path[..base_path.rfind('/').map_or(0, |i| i + 1)];
+            format!("{}{}", dir, href)
+        }
+    };
+    format!("{}://{}{}", scheme, host, remove_dot_segments(&path))
+}
+
+/// Whether the URL starts with a scheme such as `https:` or `mailto:`
+fn has_scheme(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.starts_with(|ch: char| ch.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Resolve `.` and `..` in the path, e.g. /a/./b/../c → /a/c. Query and fragment are kept as is
+fn remove_dot_segments(path: &str) -> String {
+    let (path, suffix) = path.split_at(path.find(['?', '#']).unwrap_or(path.len()));
+    let parts = path.trim_start_matches('/').split('/').collect::<Vec<_>>();
+    let mut segments = vec![];
+    for (i, part) in parts.iter().enumerate() {
+        let last = i == parts.len() - 1;
+        match *part {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(*part),
+        }
+        // `/a/..` is `/`, and `/a/.` is `/a/`
+        if last && matches!(*part, "." | "..") {
+            segments.push("");
+        }
+    }
+    format!("/{}{}", segments.join("/"), suffix)
+}
+
+/// Decode character references
+///   e.g. a&amp;b → a&b, &#65; → A, &#x41; → A
+fn decode_entities(s: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .and_then(|end| decode_entity(&rest[1..end]).map(|ch| (ch, end)));
+        match entity {
+            Some((ch, end)) => {
+                decoded.push(ch);
+                rest = &rest[end + 1..];
+            }
+            // not a character reference, e.g. `a & b`
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let code = match name.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok(),
+                Some(dec) => dec.parse().ok(),
+                None => None,
+            };
+            code.and_then(char::from_u32)
+        }
+    }
+}
+
+impl Node {
+    /// Elements which satisfy the predicate in document order, including the node itself
+    ///
+    /// e.g. node.find_all(|e| e.get_classes().is_some()) → elements with `class` attribute
+    pub fn find_all<F: Fn(&Element) -> bool>(&self, f: F) -> Vec<&Element> {
+        let mut elements = vec![];
+        self.collect_elements(&f, &mut elements);
+        elements
+    }
+
+    fn collect_elements<'a, F: Fn(&Element) -> bool>(
+        &'a self,
+        f: &F,
+        elements: &mut Vec<&'a Element>,
+    ) {
+        if let Node::Element(elem) = self {
+            if f(elem) {
+                elements.push(elem);
+            }
+            for child in elem.children.iter() {
+                child.collect_elements(f, elements);
+            }
+        }
+    }
+
+    /// The first element whose id is `id`
+    pub fn get_element_by_id(&self, id: &str) -> Option<&Element> {
+        self.find_all(|e| e.get_id() == Some(id)).into_iter().next()
+    }
+
+    pub fn get_elements_by_tag_name(&self, tag_name: &ElementTagName) -> Vec<&Element> {
+        self.find_all(|e| &e.tag_name == tag_name)
+    }
+
+    /// `href` of every <a> in document order. Relative URLs are resolved against `base`
+    /// when it is given
+    ///
+    /// e.g. <a href="/about"> with base https://example.com/docs/ → https://example.com/about
+    pub fn links(&self, base: Option<&str>) -> Vec<String> {
+        self.get_elements_by_tag_name(&ElementTagName::A)
+            .into_iter()
+            .filter_map(|e| e.get_value_by_name(&NodeKey::Href))
+            .map(|href| match base {
+                Some(base) => join_url(base, href.trim()),
+                None => href.to_string(),
+            })
+            .collect()
+    }
+
+    /// Serialize to HTML which `DocumentObjectParser` parses to the same node.
+    /// Text and attribute values are escaped
+    ///
+    /// e.g. <p class="a">x &lt; y</p>
+    pub fn to_html(&self) -> String {
+        let mut s = String::new();
+        self.write_html(&mut s);
+        s
+    }
+
+    fn write_html(&self, s: &mut String) {
+        match self {
+            Node::Element(elem) => {
+                s.push_str(&format!("<{}", elem.tag_name));
+                for (key, value) in elem.attributes.iter() {
+                    s.push_str(&format!(" {}=\"{}\"", key, escape_attribute(value)));
+                }
+                s.push('>');
+                if elem.tag_name.is_void() {
+                    return;
+                }
+                for child in elem.children.iter() {
+                    child.write_html(s);
+                }
+                s.push_str(&format!("</{}>", elem.tag_name));
+            }
+            Node::Text(text) => s.push_str(&escape_text(text)),
+            // contents of <style> is not HTML
+            Node::Style(style) => s.push_str(style),
+            Node::Comment(comment) => s.push_str(&format!("<!--{}-->", comment)),
+            Node::Doctype(doctype) => s.push_str(&format!("<!doctype {}>", doctype)),
+            Node::EndTag => {}
+        }
+    }
+
+    /// Equality of trees which ignores whitespace-only text and how text is spaced, e.g. the
+    /// same document indented differently. Attributes are compared as a map, so their order
+    /// doesn't matter
+    pub fn structurally_eq(&self, other: &Node) -> bool {
+        match (self, other) {
+            (Node::Element(a), Node::Element(b)) => {
+                let a_children = a.significant_children();
+                let b_children = b.significant_children();
+                a.tag_name == b.tag_name
+                    && a.attributes == b.attributes
+                    && a_children.len() == b_children.len()
+                    && a_children
+                        .iter()
+                        .zip(b_children)
+                        .all(|(a, b)| a.structurally_eq(b))
+            }
+            (Node::Text(a), Node::Text(b)) => a.split_whitespace().eq(b.split_whitespace()),
+            _ => self == other,
+        }
+    }
+}
+
+impl ElementBuilder {
+    /// Start building an element of tag_name without attributes and children
+    pub fn tag(tag_name: ElementTagName) -> Self {
+        Self {
+            element: Element::new(tag_name, ElementAttributes::new(), vec![]),
+        }
+    }
+
+    pub fn attr(mut self, key: NodeKey, value: &str) -> Self {
+        self.element.set_attribute(key, value.to_string());
+        self
+    }
+
+    /// Append class_name to the class list, e.g. .class("a").class("b") → class="a b"
+    pub fn class(mut self, class_name: &str) -> Self {
+        self.element.add_class(class_name);
+        self
+    }
+
+    pub fn id(self, id: &str) -> Self {
+        self.attr(NodeKey::Id, id)
+    }
+
+    pub fn child(mut self, node: Node) -> Self {
+        self.element.children.push(node);
+        self
+    }
+
+    pub fn text(self, text: &str) -> Self {
+        self.child(Node::Text(text.to_string()))
+    }
+
+    pub fn build(self) -> Node {
+        Node::Element(self.element)
+    }
 }
 
 impl Element {
@@ -261,6 +672,18 @@ impl Element {
         }
     }
 
+    /// Tag name in lowercase
+    ///   e.g. <DIV> → div, Other("Section") → section
+    pub fn tag_name_str(&self) -> String {
+        self.tag_name.to_string().to_lowercase()
+    }
+
+    /// Whether the element has the tag name, ignoring case
+    ///   e.g. <div> is "DIV" and "div"
+    pub fn is_tag(&self, tag_name: &str) -> bool {
+        self.tag_name.to_string().eq_ignore_ascii_case(tag_name)
+    }
+
     pub fn get_id(&self) -> Option<&str> {
         self.get_value_by_name(&NodeKey::Id)
     }
@@ -269,6 +692,88 @@ impl Element {
         self.get_value_by_name(&NodeKey::Class)
     }
 
+    /// Whether class list of `class` attribute contains class_name
+    pub fn has_class(&self, class_name: &str) -> bool {
+        self.get_classes()
+            .unwrap_or_default()
+            .split_whitespace()
+            .any(|class| class == class_name)
+    }
+
+    /// Value of `style` attribute
+    ///   e.g. <p style="color:#f00;margin:4px"> → color:#f00;margin:4px
+    pub fn get_inline_style(&self) -> Option<&str> {
+        self.get_value_by_name(&NodeKey::Style)
+    }
+
+    /// Value of `data-*` attribute by the name without `data-`
+    ///   e.g. <div data-id="5"> → data("id") is Some("5")
+    pub fn data(&self, name: &str) -> Option<&str> {
+        self.get_value_by_name(&NodeKey::Data(name.to_lowercase()))
+    }
+
+    /// Set the attribute, replacing the current value
+    pub fn set_attribute(&mut self, key: NodeKey, value: String) {
+        self.attributes.insert(key, value);
+    }
+
+    /// Remove the attribute and return its value
+    pub fn remove_attribute(&mut self, key: &NodeKey) -> Option<String> {
+        self.attributes.remove(key)
+    }
+
+    /// Append class_name to the class list unless it already has the class
+    ///   e.g. add_class("b") to <div class="a"> → <div class="a b">
+    pub fn add_class(&mut self, class_name: &str) {
+        if self.has_class(class_name) {
+            return;
+        }
+        let mut classes = self
+            .get_classes()
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        classes.push(class_name);
+        let classes = classes.join(" ");
+        self.set_attribute(NodeKey::Class, classes);
+    }
+
+    /// Remove class_name from the class list. `class` attribute is removed when it gets empty
+    pub fn remove_class(&mut self, class_name: &str) {
+        let classes = self
+            .get_classes()
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter(|class| *class != class_name)
+            .collect::<Vec<_>>()
+            .join(" ");
+        if classes.is_empty() {
+            self.remove_attribute(&NodeKey::Class);
+        } else {
+            self.set_attribute(NodeKey::Class, classes);
+        }
+    }
+
+    /// Whether `other` is a descendant of the element. It is found by pointer or, for a copy
+    /// of the element, by equality. The element itself is not its descendant
+    ///   e.g. <div><p><b>x</b></p></div> contains <p> and <b>, but <p> doesn't contain <div>
+    pub fn contains(&self, other: &Element) -> bool {
+        self.children.iter().any(|child| match child {
+            Node::Element(elem) => {
+                std::ptr::eq(elem, other) || elem == other || elem.contains(other)
+            }
+            _ => false,
+        })
+    }
+
+    /// Children except whitespace-only text
+    fn significant_children(&self) -> Vec<&Node> {
+        self.children
+            .iter()
+            .filter(|node| !matches!(node, Node::Text(text) if text.trim().is_empty()))
+            .collect()
+    }
+
     fn get_value_by_name(&self, node_key: &NodeKey) -> Option<&str> {
         for (key, value) in self.attributes.iter() {
             if key == node_key {
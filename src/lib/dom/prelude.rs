@@ -3,9 +3,31 @@ use std::fmt;
 use std::iter::Peekable;
 use std::str::Chars;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 /// Parser that convert raw HTML input to DOM
 pub struct DocumentObjectParser<'a> {
     pub(crate) input: Peekable<Chars<'a>>,
+    // length of input in characters, to report the position of errors
+    pub(crate) len: usize,
+    // inside <pre> or <textarea>, where whitespace of text is kept
+    pub(crate) preformatted: bool,
+}
+
+/// Error of parsing HTML
+#[derive(Debug, PartialEq, Clone)]
+pub enum HtmlParseError {
+    // character and its offset in characters, e.g. `<` of `<div <!-- x --> >`
+    UnexpectedChar(char, usize),
+    // input has only text, comments or doctype
+    NoRootElement,
+    // input ends inside an element, e.g. <pre>a
+    UnexpectedEof,
+    // tag without tag name and its offset in characters, e.g. `>` of `<>`
+    EmptyTag(usize),
+    // e.g. `>` of `</>`
+    EmptyEndTag(usize),
 }
 
 /// HTML node
@@ -14,27 +36,31 @@ pub struct DocumentObjectParser<'a> {
 ///   Hello, world
 ///   <!-- implement here -->
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Node {
     Text(String),
     Style(String),
     Element(Element),
     Comment(String),
+    // e.g. html of <!doctype html>
+    Doctype(String),
     EndTag, // Document,
 }
 
 impl Node {
-    // TODO: refactor
-    pub fn extract_style(&self) -> String {
-        if let Node::Style(style) = self.find_style().unwrap().children[0].clone() {
-            style
-        } else {
-            "".to_string()
-        }
+    /// Concatenate text of every <style> element in document order. Style which has `media`
+    /// attribute is wrapped in `@media`, so that it applies only for the media
+    ///
+    /// e.g. <style media="print">p { color: red }</style> → @media print { p { color: red } }
+    pub fn extract_styles(&self) -> String {
+        let mut styles = vec![];
+        self.collect_styles(&mut styles);
+        styles.join("\n")
     }
-    fn find_style(&self) -> Option<Element> {
-        match self {
-            Node::Element(elem) => elem.find_style(),
-            _ => None,
+
+    fn collect_styles(&self, styles: &mut Vec<String>) {
+        if let Node::Element(elem) = self {
+            elem.collect_styles(styles);
         }
     }
 
@@ -51,37 +77,54 @@ impl Node {
 /// e.g.
 ///   <div class="table" id="consultation">
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Element {
     pub tag_name: ElementTagName,
     pub attributes: ElementAttributes,
     pub children: Vec<Node>,
 }
 
+/// Builder of Element tree for code which constructs DOM without parsing HTML
+///
+/// e.g.
+///   ElementBuilder::tag(Div).id("names").child(ElementBuilder::tag(P).text("Alice").build()).build()
+///   → <div id="names"><p>Alice</p></div>
+#[derive(Debug, Clone)]
+pub struct ElementBuilder {
+    pub(crate) element: Element,
+}
+
 impl Element {
-    fn find_style(&self) -> Option<Self> {
-        if self.children.is_empty() {
-            return None;
-        }
-        if self.is_style() {
-            return Some(self.clone());
-        }
+    fn collect_styles(&self, styles: &mut Vec<String>) {
         for child in self.children.iter() {
-            let elem = child.find_style();
-            if elem.is_some() {
-                return elem;
+            match child {
+                Node::Style(style) if self.is_style() => match self.get_media() {
+                    Some(media) => styles.push(format!("@media {} {{\n{}\n}}", media, style)),
+                    None => styles.push(style.clone()),
+                },
+                _ => child.collect_styles(styles),
             }
         }
-        None
     }
 
     fn is_style(&self) -> bool {
         self.tag_name == ElementTagName::Style
     }
+
+    /// `media` attribute of <style>. None when it applies for every media, e.g. media="all"
+    fn get_media(&self) -> Option<&str> {
+        let media = self.get_value_by_name(&NodeKey::from("media"))?.trim();
+        match media.to_lowercase().as_str() {
+            "" | "all" | "screen" => None,
+            _ => Some(media),
+        }
+    }
 }
 
 /// HTML Element tagName
 /// e.g. div of <div>
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ElementTagName {
     Html,
     Main,
@@ -99,9 +142,53 @@ pub enum ElementTagName {
     H2,
     H3,
     A,
+    Pre,
+    Textarea,
+    Br,
+    Img,
+    Input,
+    Span,
+    Ul,
+    Li,
+    Link,
+    Table,
+    Tr,
+    Td,
+    Section,
+    Nav,
+    Header,
+    Footer,
+    Aside,
+    Figure,
+    Figcaption,
+    Button,
     Other(String),
 }
 
+impl ElementTagName {
+    /// Void element has no children and no end tag, e.g. <br>, <img src="a.png">
+    pub fn is_void(&self) -> bool {
+        match self {
+            Self::Meta | Self::Br | Self::Img | Self::Input | Self::Link => true,
+            Self::Other(tag_name) => matches!(
+                tag_name.as_str(),
+                "area" | "base" | "col" | "embed" | "hr" | "source" | "track" | "wbr"
+            ),
+            _ => false,
+        }
+    }
+
+    /// Level of heading element, e.g. <h2> → 2. Only h1 to h3 are known
+    pub fn heading_level(&self) -> Option<usize> {
+        match self {
+            Self::H1 => Some(1),
+            Self::H2 => Some(2),
+            Self::H3 => Some(3),
+            _ => None,
+        }
+    }
+}
+
 /// HTML Element attributes
 /// e.g.
 ///   class: "table"
@@ -111,23 +198,52 @@ pub type ElementAttributes = BTreeMap<NodeKey, String>;
 /// HTML Element key
 /// e.g. id of <div id="test">
 #[derive(Debug, PartialEq, Eq, Clone, Hash, Ord, PartialOrd)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "String", from = "String")
+)]
 pub enum NodeKey {
     Id,
     Class,
     Href,
+    Style,
+    // data-* attribute keyed by the suffix, e.g. id of data-id
+    Data(String),
     Other(String),
 }
 
+impl fmt::Display for HtmlParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HtmlParseError::UnexpectedChar(ch, position) => {
+                write!(f, "unexpected character '{}' at {}", ch, position)
+            }
+            HtmlParseError::NoRootElement => write!(f, "no root element"),
+            HtmlParseError::UnexpectedEof => write!(f, "unexpected end of input"),
+            HtmlParseError::EmptyTag(position) => write!(f, "empty tag <> at {}", position),
+            HtmlParseError::EmptyEndTag(position) => write!(f, "empty end tag </> at {}", position),
+        }
+    }
+}
+
+impl std::error::Error for HtmlParseError {}
+
+/// Tag name as written in HTML, e.g. Div → div, Other("dialog") → dialog
 impl fmt::Display for ElementTagName {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s = format!("{:?}", self).to_lowercase();
-        write!(f, "{}", s)
+        match self {
+            Self::Other(tag_name) => write!(f, "{}", tag_name),
+            _ => write!(f, "{}", format!("{:?}", self).to_lowercase()),
+        }
     }
 }
 
+/// Tag name is case-insensitive, e.g. DIV → Div
 impl<'a> From<&'a str> for ElementTagName {
     fn from(tag_name: &'a str) -> Self {
-        match tag_name {
+        let tag_name = tag_name.to_lowercase();
+        match tag_name.as_str() {
             "html" => Self::Html,
             "main" => Self::Main,
             "head" => Self::Head,
@@ -142,18 +258,70 @@ impl<'a> From<&'a str> for ElementTagName {
             "h2" => Self::H2,
             "h3" => Self::H3,
             "a" => Self::A,
-            _ => Self::Other(tag_name.to_string()),
+            "pre" => Self::Pre,
+            "textarea" => Self::Textarea,
+            "br" => Self::Br,
+            "img" => Self::Img,
+            "input" => Self::Input,
+            "span" => Self::Span,
+            "ul" => Self::Ul,
+            "li" => Self::Li,
+            "link" => Self::Link,
+            "table" => Self::Table,
+            "tr" => Self::Tr,
+            "td" => Self::Td,
+            "section" => Self::Section,
+            "nav" => Self::Nav,
+            "header" => Self::Header,
+            "footer" => Self::Footer,
+            "aside" => Self::Aside,
+            "figure" => Self::Figure,
+            "figcaption" => Self::Figcaption,
+            "button" => Self::Button,
+            _ => Self::Other(tag_name),
         }
     }
 }
 
+impl fmt::Display for NodeKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Self::Id => "id",
+            Self::Class => "class",
+            Self::Href => "href",
+            Self::Style => "style",
+            Self::Data(name) => return write!(f, "data-{}", name),
+            Self::Other(key) => key,
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl From<String> for NodeKey {
+    fn from(key: String) -> Self {
+        Self::from(key.as_str())
+    }
+}
+
+impl From<NodeKey> for String {
+    fn from(key: NodeKey) -> Self {
+        key.to_string()
+    }
+}
+
+/// Attribute name is case-insensitive, e.g. CLASS → Class
 impl<'a> From<&'a str> for NodeKey {
     fn from(key: &'a str) -> Self {
-        match key {
+        let key = key.to_lowercase();
+        match key.as_str() {
             "id" => Self::Id,
             "class" => Self::Class,
             "href" => Self::Href,
-            _ => Self::Other(key.to_string()),
+            "style" => Self::Style,
+            _ => match key.strip_prefix("data-") {
+                Some(name) if !name.is_empty() => Self::Data(name.to_string()),
+                _ => Self::Other(key),
+            },
         }
     }
 }
@@ -127,3 +127,556 @@
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::ElementTagName::*;
+    use crate::lib::*;
+
+    fn parse(input: &str) -> Result<Node, HtmlParseError> {
+        DocumentObjectParser::new(input).parse()
+    }
+
+    #[test]
+    fn test_preformatted_descendants() {
+        assert_eq!(
+            parse("<pre><span>  a  </span> b\n</pre>"),
+            Ok(ElementBuilder::tag(Pre)
+                .child(ElementBuilder::tag(Span).text("  a  ").build())
+                .text(" b\n")
+                .build())
+        );
+        // whitespace outside <pre> is collapsed again
+        assert_eq!(
+            parse("<div><pre> a </pre><span>  b  </span></div>"),
+            Ok(ElementBuilder::tag(Div)
+                .child(ElementBuilder::tag(Pre).text(" a ").build())
+                .child(ElementBuilder::tag(Span).text("b").build())
+                .build())
+        );
+    }
+
+    #[test]
+    fn test_unterminated_preformatted() {
+        assert_eq!(parse("<pre>  a"), Err(HtmlParseError::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_empty_tag() {
+        assert_eq!(parse("<div><></div>"), Err(HtmlParseError::EmptyTag(6)));
+        assert_eq!(
+            parse("<div>a</></div>"),
+            Err(HtmlParseError::EmptyEndTag(8))
+        );
+        assert_eq!(
+            HtmlParseError::EmptyEndTag(8).to_string(),
+            "empty end tag </> at 8"
+        );
+    }
+
+    #[test]
+    fn test_extract_styles() {
+        let dom = parse(
+            "<html><head><style>p { color: red }</style></head>\
+             <body><style>div { margin: 0 }</style><p>a</p></body></html>",
+        )
+        .unwrap();
+        let stylesheet = StyleSheetParser::new(&dom.extract_styles())
+            .parse()
+            .unwrap();
+        let selectors = stylesheet
+            .rules()
+            .iter()
+            .map(|rule| rule.selectors().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            selectors,
+            vec![
+                vec![Selector::parse("p").unwrap()],
+                vec![Selector::parse("div").unwrap()]
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let dom = parse(
+            r#"<div class="a" data-x="1"><p style="color: red">Hello</p><!-- c --><br/></div>"#,
+        )
+        .unwrap();
+        let json = serde_json::to_string(&dom).unwrap();
+        assert_eq!(serde_json::from_str::<Node>(&json).unwrap(), dom);
+
+        let stylesheet = StyleSheetParser::new(
+            "div.a > p:first-child { margin: 0 auto; color: #ff000080 } \
+             @media (max-width: 600px) { p { font-size: 1.5em !important } }",
+        )
+        .parse()
+        .unwrap();
+        let json = serde_json::to_string(&stylesheet).unwrap();
+        assert_eq!(
+            serde_json::from_str::<StyleSheet>(&json).unwrap(),
+            stylesheet
+        );
+    }
+
+    #[test]
+    fn test_uppercase_names() {
+        assert_eq!(
+            parse(r#"<DIV CLASS="X" Data-Name="Y">Text</DIV>"#),
+            parse(r#"<div class="X" data-name="Y">Text</div>"#)
+        );
+        assert_eq!(
+            parse(r#"<DIV CLASS="X">Text</DIV>"#),
+            Ok(ElementBuilder::tag(Div).class("X").text("Text").build())
+        );
+    }
+
+    #[test]
+    fn test_attribute_value() {
+        assert_eq!(
+            parse(r#"<a title='He said "hi"' href="x?a&amp;b">link</a>"#),
+            Ok(ElementBuilder::tag(A)
+                .attr(NodeKey::Other("title".to_string()), r#"He said "hi""#)
+                .attr(NodeKey::Href, "x?a&b")
+                .text("link")
+                .build())
+        );
+    }
+
+    #[test]
+    fn test_self_closing() {
+        let expected = Ok(ElementBuilder::tag(Div)
+            .child(ElementBuilder::tag(Br).build())
+            .child(
+                ElementBuilder::tag(Img)
+                    .attr(NodeKey::Other("src".to_string()), "x")
+                    .build(),
+            )
+            .child(ElementBuilder::tag(Other("hr".to_string())).build())
+            .build());
+        assert_eq!(parse(r#"<div><br/><img src="x"/><hr/></div>"#), expected);
+        assert_eq!(parse(r#"<div><br /><img src="x" /><hr /></div>"#), expected);
+        assert_eq!(
+            parse(r#"<div><br / ><img src="x"  /><hr   /></div>"#),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_to_html_round_trip() {
+        let node = ElementBuilder::tag(P)
+            .attr(NodeKey::Other("title".to_string()), r#"say "hi" & go"#)
+            .text("a < b & c > d")
+            .build();
+        let html = node.to_html();
+        assert_eq!(
+            html,
+            r#"<p title="say &quot;hi&quot; &amp; go">a &lt; b &amp; c &gt; d</p>"#
+        );
+        assert_eq!(parse(&html), Ok(node));
+    }
+
+    #[test]
+    fn test_comment_with_dashes() {
+        assert_eq!(
+            DocumentObjectParser::new("<!-- a - b --><!--- leading dash ---><p>x</p>")
+                .parse_document(),
+            Ok(vec![
+                Node::Comment("a - b".to_string()),
+                Node::Comment("- leading dash -".to_string()),
+                ElementBuilder::tag(P).text("x").build(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_tag_name_str() {
+        let div = Element::new(Div, ElementAttributes::new(), vec![]);
+        let section = Element::new(
+            Other("Section".to_string()),
+            ElementAttributes::new(),
+            vec![],
+        );
+        assert_eq!(div.tag_name_str(), "div");
+        assert_eq!(section.tag_name_str(), "section");
+        assert!(div.is_tag("DIV"));
+        assert!(section.is_tag("section"));
+        assert!(section.is_tag("SECTION"));
+        assert!(!section.is_tag("div"));
+    }
+
+    #[test]
+    fn test_parse_document() {
+        assert_eq!(
+            DocumentObjectParser::new("<!-- a --><div/><div/>").parse_document(),
+            Ok(vec![
+                Node::Comment("a".to_string()),
+                ElementBuilder::tag(Div).build(),
+                ElementBuilder::tag(Div).build(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_example_document() {
+        let html = include_str!("../example.html");
+        let node = parse(html).unwrap();
+        match node {
+            Node::Element(ref elem) => assert_eq!(elem.tag_name, Html),
+            _ => panic!("expected the html element, got {:?}", node),
+        }
+        let nodes = DocumentObjectParser::new(html).parse_document().unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0], Node::Doctype("html".to_string()));
+        assert_eq!(nodes[1], node);
+    }
+
+    #[test]
+    fn test_doctype() {
+        assert_eq!(
+            DocumentObjectParser::new("<!doctype html><html></html>").parse_document(),
+            Ok(vec![
+                Node::Doctype("html".to_string()),
+                ElementBuilder::tag(Html).build(),
+            ])
+        );
+        // the keyword is case-insensitive
+        assert_eq!(
+            DocumentObjectParser::new("<!DOCTYPE html><html></html>").parse_document(),
+            Ok(vec![
+                Node::Doctype("html".to_string()),
+                ElementBuilder::tag(Html).build(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_duplicate_attribute() {
+        let node = parse(r#"<div class="a" id="x" class="b" ID="y"></div>"#).unwrap();
+        // the first value wins, and names are case-insensitive
+        assert_eq!(node, ElementBuilder::tag(Div).class("a").id("x").build());
+    }
+
+    #[test]
+    fn test_attribute_mutation() {
+        let mut element = Element::new(
+            Div,
+            ElementAttributes::from_iter([(NodeKey::Class, "a".to_string())]),
+            vec![],
+        );
+        element.add_class("b");
+        assert_eq!(element.get_classes(), Some("a b"));
+        // a class isn't added twice
+        element.add_class("a");
+        assert_eq!(element.get_classes(), Some("a b"));
+        element.remove_class("a");
+        assert_eq!(element.get_classes(), Some("b"));
+        element.remove_class("b");
+        assert_eq!(element.get_classes(), None);
+
+        element.set_attribute(NodeKey::Id, "x".to_string());
+        element.set_attribute(NodeKey::Id, "y".to_string());
+        assert_eq!(element.get_id(), Some("y"));
+        assert_eq!(
+            element.remove_attribute(&NodeKey::Id),
+            Some("y".to_string())
+        );
+        assert_eq!(element.get_id(), None);
+    }
+
+    #[test]
+    fn test_element_builder() {
+        let parsed = parse(
+            r#"<div id="names"><p class="name first">Alice</p><p class="name">Bob</p></div>"#,
+        );
+        let built = ElementBuilder::tag(Div)
+            .id("names")
+            .child(
+                ElementBuilder::tag(P)
+                    .class("name")
+                    .class("first")
+                    .text("Alice")
+                    .build(),
+            )
+            .child(ElementBuilder::tag(P).class("name").text("Bob").build())
+            .build();
+        assert_eq!(parsed, Ok(built));
+    }
+
+    #[test]
+    fn test_tag_name_display() {
+        assert_eq!(Other("section".to_string()).to_string(), "section");
+        assert_eq!(Other("my-widget".to_string()).to_string(), "my-widget");
+        assert_eq!(Div.to_string(), "div");
+        assert_eq!(
+            ElementBuilder::tag(Other("dialog".to_string()))
+                .build()
+                .to_html(),
+            "<dialog></dialog>"
+        );
+    }
+
+    #[test]
+    fn test_whitespace_around_equals() {
+        assert_eq!(
+            parse(r#"<div id =  "note" class= "x"></div>"#),
+            parse(r#"<div id="note" class="x"></div>"#)
+        );
+        assert_eq!(
+            parse(r#"<div class = "name" id = "note"></div>"#),
+            Ok(ElementBuilder::tag(Div).class("name").id("note").build())
+        );
+    }
+
+    #[test]
+    fn test_parse_fragment() {
+        assert_eq!(
+            DocumentObjectParser::new("Hello <b>world</b>!").parse_fragment(),
+            Ok(vec![
+                Node::Text("Hello".to_string()),
+                ElementBuilder::tag(Other("b".to_string()))
+                    .text("world")
+                    .build(),
+                Node::Text("!".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_collapse_whitespace() {
+        assert_eq!(
+            parse("<p>  a   b  </p>"),
+            Ok(ElementBuilder::tag(P).text("a b").build())
+        );
+        assert_eq!(
+            parse("<p>a\n\t  b</p>"),
+            Ok(ElementBuilder::tag(P).text("a b").build())
+        );
+        // <pre> keeps whitespace as written
+        assert_eq!(
+            parse("<pre>  a   b  </pre>"),
+            Ok(ElementBuilder::tag(Pre).text("  a   b  ").build())
+        );
+    }
+
+    #[test]
+    fn test_comment_inside_tag() {
+        // `<` of the comment is at 5
+        assert_eq!(
+            parse("<div <!-- x --> >a</div>"),
+            Err(HtmlParseError::UnexpectedChar('<', 5))
+        );
+    }
+
+    #[test]
+    fn test_find_all() {
+        let node = parse(
+            r#"<div class="a"><p>x</p><p class="b" id="y">y<span class="c">z</span></p></div>"#,
+        )
+        .unwrap();
+        let classes = node
+            .find_all(|e| e.get_classes().is_some())
+            .iter()
+            .map(|e| e.get_classes().unwrap())
+            .collect::<Vec<_>>();
+        // in document order, including the node itself
+        assert_eq!(classes, vec!["a", "b", "c"]);
+        assert_eq!(
+            node.get_element_by_id("y").unwrap().get_classes(),
+            Some("b")
+        );
+        assert_eq!(node.get_elements_by_tag_name(&P).len(), 2);
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        assert_eq!(
+            parse("\u{feff}<html><p>a</p></html>"),
+            parse("<html><p>a</p></html>")
+        );
+        assert_eq!(
+            DocumentObjectParser::new("\u{feff}<!doctype html><html></html>").parse_document(),
+            Ok(vec![
+                Node::Doctype("html".to_string()),
+                ElementBuilder::tag(Html).build(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_contains() {
+        let node = parse("<div><p><b>x</b></p><span>y</span></div>").unwrap();
+        let div = match &node {
+            Node::Element(div) => div,
+            _ => unreachable!(),
+        };
+        let p = node.get_elements_by_tag_name(&P)[0];
+        let b = node.get_elements_by_tag_name(&Other("b".to_string()))[0];
+        let span = node.get_elements_by_tag_name(&Span)[0];
+        assert!(div.contains(p));
+        assert!(div.contains(b));
+        assert!(p.contains(b));
+        assert!(!p.contains(div));
+        assert!(!p.contains(span));
+        assert!(!div.contains(div));
+        // a copy of the descendant is found by equality
+        assert!(div.contains(&b.clone()));
+    }
+
+    #[test]
+    fn test_semantic_tag_names() {
+        let tags = [
+            ("section", Section),
+            ("nav", Nav),
+            ("header", Header),
+            ("footer", Footer),
+            ("aside", Aside),
+            ("figure", Figure),
+            ("figcaption", Figcaption),
+            ("button", Button),
+        ];
+        for (name, tag) in tags {
+            assert_eq!(ElementTagName::from(name), tag);
+            assert_eq!(ElementTagName::from(name.to_uppercase().as_str()), tag);
+            assert_eq!(tag.to_string(), name);
+        }
+    }
+
+    #[test]
+    fn test_greater_than_in_text() {
+        assert_eq!(
+            parse("<p>a > b</p>"),
+            Ok(ElementBuilder::tag(P).text("a > b").build())
+        );
+        assert_eq!(
+            parse("<code>x >= 1 && y > 2</code>"),
+            Ok(ElementBuilder::tag(Other("code".to_string()))
+                .text("x >= 1 && y > 2")
+                .build())
+        );
+    }
+
+    #[test]
+    fn test_structurally_eq() {
+        let compact = parse(r#"<div id="a" class="b"><p>x</p><p>y</p></div>"#).unwrap();
+        let indented = parse(
+            r#"<div class="b" id="a">
+                <p>x</p>
+                <p>y</p>
+            </div>"#,
+        )
+        .unwrap();
+        assert!(compact.structurally_eq(&indented));
+        assert!(indented.structurally_eq(&compact));
+        // whitespace-only text between elements is insignificant
+        let spaced = ElementBuilder::tag(Div)
+            .id("a")
+            .class("b")
+            .text("\n  ")
+            .child(ElementBuilder::tag(P).text("x").build())
+            .text(" ")
+            .child(ElementBuilder::tag(P).text("y").build())
+            .build();
+        assert_ne!(compact, spaced);
+        assert!(compact.structurally_eq(&spaced));
+        let different = parse(r#"<div id="a" class="b"><p>x</p><p>z</p></div>"#).unwrap();
+        assert!(!compact.structurally_eq(&different));
+    }
+
+    #[test]
+    fn test_data_attribute() {
+        let html = r#"<div data-id="5" data-user-name="x"></div>"#;
+        let node = parse(html).unwrap();
+        let div = match &node {
+            Node::Element(div) => div,
+            _ => panic!("expected element"),
+        };
+        assert_eq!(div.data("id"), Some("5"));
+        assert_eq!(div.data("user-name"), Some("x"));
+        assert_eq!(div.data("missing"), None);
+        assert_eq!(
+            div.get_value_by_name(&NodeKey::Data("id".to_string())),
+            Some("5")
+        );
+        assert_eq!(NodeKey::Data("id".to_string()).to_string(), "data-id");
+        assert_eq!(node.to_html(), html);
+        assert_eq!(parse(&node.to_html()), Ok(node));
+    }
+
+    #[test]
+    fn test_links() {
+        let node = parse(include_str!("../example.html")).unwrap();
+        assert_eq!(
+            node.links(None),
+            vec!["https://www.iana.org/domains/example"]
+        );
+        // absolute URLs are kept as they are
+        assert_eq!(
+            node.links(Some("https://example.com/")),
+            vec!["https://www.iana.org/domains/example"]
+        );
+    }
+
+    #[test]
+    fn test_links_relative() {
+        let node = parse(
+            r##"<div>
+                <a href="/about">a</a>
+                <p><a href="b.html">b</a></p>
+                <a href="../c?q=1">c</a>
+                <a href="#top">d</a>
+                <a href="//cdn.example.com/x">e</a>
+                <a>no href</a>
+            </div>"##,
+        )
+        .unwrap();
+        assert_eq!(
+            node.links(Some("https://example.com/docs/a.html")),
+            vec![
+                "https://example.com/about",
+                "https://example.com/docs/b.html",
+                "https://example.com/c?q=1",
+                "https://example.com/docs/a.html#top",
+                "https://cdn.example.com/x",
+            ]
+        );
+        // without base, hrefs are returned as written
+        assert_eq!(
+            node.links(None),
+            vec![
+                "/about",
+                "b.html",
+                "../c?q=1",
+                "#top",
+                "//cdn.example.com/x"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_namespaced_and_custom_tags() {
+        assert_eq!(
+            parse(r#"<svg><svg:rect width="10"></svg:rect></svg>"#),
+            Ok(ElementBuilder::tag(Other("svg".to_string()))
+                .child(
+                    ElementBuilder::tag(Other("svg:rect".to_string()))
+                        .attr(NodeKey::Other("width".to_string()), "10")
+                        .build()
+                )
+                .build())
+        );
+        assert_eq!(
+            parse("<div><my-widget>x</my-widget><p>y</p></div>"),
+            Ok(ElementBuilder::tag(Div)
+                .child(
+                    ElementBuilder::tag(Other("my-widget".to_string()))
+                        .text("x")
+                        .build()
+                )
+                .child(ElementBuilder::tag(P).text("y").build())
+                .build())
+        );
+    }
+}
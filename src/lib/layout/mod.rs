@@ -0,0 +1,249 @@
+mod test;
+
+use super::*;
+
+/// Box of a render object placed by `RenderObject::layout`, in px from the top-left of the page.
+/// The box includes padding, but not margin
+#[derive(Debug, Clone)]
+pub struct LayoutBox<'a> {
+    pub render_object: &'a RenderObject,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub children: Vec<LayoutBox<'a>>,
+}
+
+// Text is measured without fonts, as if every character were half as wide as the font size
+const CHAR_WIDTH_RATIO: f64 = 0.5;
+
+// Line height of `line-height: normal` relative to the font size
+const NORMAL_LINE_HEIGHT: f64 = 1.2;
+
+impl RenderObject {
+    /// Lay the render tree out in the viewport width without GUI, e.g. for layout tests.
+    ///
+    /// It is a simplified normal flow: block children stack vertically, and inline children
+    /// line up from the left and wrap when the line is full. Margins don't collapse, and flex and
+    /// grid containers are laid out as blocks. `width`/`height` include padding as in the browser
+    pub fn layout(&self, width: f64) -> LayoutBox<'_> {
+        let (margin_left, box_width) = block_width(self, width);
+        let margin_top = self.get_length_in(&DeclarationProperty::MarginTop, width);
+        layout_box(self, margin_left, margin_top, box_width, width)
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Dump the layout tree with position and size of each box, nested by indentation.
+    /// Numbers are rounded to 0.1px, so that the output is stable
+    ///
+    /// e.g.
+    ///   div x=0 y=0 width=300 height=19.2
+    ///     p x=0 y=0 width=300 height=19.2
+    ///       "Hello" x=0 y=0 width=40 height=19.2
+    pub fn to_debug_boxes(&self) -> String {
+        let mut s = String::new();
+        self.write_boxes(0, &mut s);
+        s
+    }
+
+    fn write_boxes(&self, depth: usize, s: &mut String) {
+        s.push_str(&format!(
+            "{}{} x={} y={} width={} height={}\n",
+            "  ".repeat(depth),
+            self.render_object.label(),
+            round(self.x),
+            round(self.y),
+            round(self.width),
+            round(self.height)
+        ));
+        for child in self.children.iter() {
+            child.write_boxes(depth + 1, s);
+        }
+    }
+}
+
+/// Place the object at (x, y) with the width, and its descendants inside it.
+/// `%` of padding is relative to containing_width, i.e. the content width of the parent
+fn layout_box(
+    object: &RenderObject,
+    x: f64,
+    y: f64,
+    width: f64,
+    containing_width: f64,
+) -> LayoutBox<'_> {
+    if let Node::Text(text) = &object.node {
+        let font_size = object.get_font_size();
+        let lines = wrap_text(text, font_size, width);
+        let line_height = object
+            .get_line_height(font_size)
+            .unwrap_or(font_size * NORMAL_LINE_HEIGHT);
+        return LayoutBox {
+            render_object: object,
+            x,
+            y,
+            width: lines.iter().copied().fold(0.0, f64::max),
+            height: lines.len() as f64 * line_height,
+            children: vec![],
+        };
+    }
+
+    let padding = |property| object.get_length_in(property, containing_width);
+    let padding_left = padding(&DeclarationProperty::PaddingLeft);
+    let padding_top = padding(&DeclarationProperty::PaddingTop);
+    let padding_right = padding(&DeclarationProperty::PaddingRight);
+    let padding_bottom = padding(&DeclarationProperty::PaddingBottom);
+    let content_x = x + padding_left;
+    let content_width = (width - padding_left - padding_right).max(0.0);
+
+    let mut children = vec![];
+    let mut cursor_y = y + padding_top;
+    for group in object.child_groups() {
+        match group.as_slice() {
+            [child] if !child.is_inline() => {
+                let (margin_left, child_width) = block_width(child, content_width);
+                let margin_top =
+                    child.get_length_in(&DeclarationProperty::MarginTop, content_width);
+                let margin_bottom =
+                    child.get_length_in(&DeclarationProperty::MarginBottom, content_width);
+                let child_box = layout_box(
+                    child,
+                    content_x + margin_left,
+                    cursor_y + margin_top,
+                    child_width,
+                    content_width,
+                );
+                cursor_y += margin_top + child_box.height + margin_bottom;
+                children.push(child_box);
+            }
+            _ => cursor_y = layout_line(&group, content_x, cursor_y, content_width, &mut children),
+        }
+    }
+
+    let height = match object.get_height() {
+        Some(height) => height,
+        None => object.clamp_height(cursor_y - y + padding_bottom),
+    };
+    LayoutBox {
+        render_object: object,
+        x,
+        y,
+        width,
+        height,
+        children,
+    }
+}
+
+/// Place inline objects side by side from the left, and wrap to the next line when the line is
+/// full. `%` is relative to the width of the line. Returns the bottom of the last line
+fn layout_line<'a>(
+    group: &[&'a RenderObject],
+    x: f64,
+    y: f64,
+    width: f64,
+    boxes: &mut Vec<LayoutBox<'a>>,
+) -> f64 {
+    let mut cursor_x = 0.0;
+    let mut line_y = y;
+    let mut line_height = 0.0_f64;
+    for child in group {
+        let margin = |property| child.get_length_in(property, width);
+        let margin_left = margin(&DeclarationProperty::MarginLeft);
+        let margin_right = margin(&DeclarationProperty::MarginRight);
+        let margin_top = margin(&DeclarationProperty::MarginTop);
+        let margin_bottom = margin(&DeclarationProperty::MarginBottom);
+        let child_width =
+            natural_width(child, width).min((width - margin_left - margin_right).max(0.0));
+        if cursor_x > 0.0 && cursor_x + margin_left + child_width + margin_right > width {
+            line_y += line_height;
+            cursor_x = 0.0;
+            line_height = 0.0;
+        }
+        let child_box = layout_box(
+            child,
+            x + cursor_x + margin_left,
+            line_y + margin_top,
+            child_width,
+            width,
+        );
+        cursor_x += margin_left + child_box.width + margin_right;
+        line_height = line_height.max(margin_top + child_box.height + margin_bottom);
+        boxes.push(child_box);
+    }
+    line_y + line_height
+}
+
+/// Left margin and width of a block in the containing width. A block fills the containing width
+/// within min-width/max-width unless its width is specified, and `margin: 0 auto` centers a block
+/// which is narrower than the containing width. `%` is relative to the containing width
+fn block_width(object: &RenderObject, containing_width: f64) -> (f64, f64) {
+    let margin_left = object.get_length_in(&DeclarationProperty::MarginLeft, containing_width);
+    let margin_right = object.get_length_in(&DeclarationProperty::MarginRight, containing_width);
+    let centered = is_auto(object, &DeclarationProperty::MarginLeft)
+        && is_auto(object, &DeclarationProperty::MarginRight);
+    let width = match object.get_width_in(containing_width) {
+        Some(width) => width,
+        None => {
+            let width = (containing_width - margin_left - margin_right).max(0.0);
+            object.clamp_width(width, containing_width)
+        }
+    };
+    match centered {
+        true => ((containing_width - width).max(0.0) / 2.0, width),
+        false => (margin_left, width),
+    }
+}
+
+fn is_auto(object: &RenderObject, property: &DeclarationProperty) -> bool {
+    matches!(
+        object.value(property),
+        Some(DeclarationValue::Length(Length::Auto))
+    )
+}
+
+/// Width of the object without wrapping, i.e. its text in a single line.
+/// `%` is relative to the containing width
+fn natural_width(object: &RenderObject, containing_width: f64) -> f64 {
+    match &object.node {
+        Node::Text(text) => wrap_text(text, object.get_font_size(), f64::INFINITY)
+            .first()
+            .copied()
+            .unwrap_or(0.0),
+        _ => object.get_width_in(containing_width).unwrap_or_else(|| {
+            let padding = object.get_length_in(&DeclarationProperty::PaddingLeft, containing_width)
+                + object.get_length_in(&DeclarationProperty::PaddingRight, containing_width);
+            let content = object
+                .children
+                .iter()
+                .map(|child| {
+                    child.get_length_in(&DeclarationProperty::MarginLeft, containing_width)
+                        + natural_width(child, containing_width)
+                        + child.get_length_in(&DeclarationProperty::MarginRight, containing_width)
+                })
+                .sum::<f64>();
+            object.clamp_width(padding + content, containing_width)
+        }),
+    }
+}
+
+/// Widths of the lines which the text wraps into in the width, breaking between words.
+/// A word wider than the width makes a line by itself
+fn wrap_text(text: &str, font_size: f64, width: f64) -> Vec<f64> {
+    let char_width = font_size * CHAR_WIDTH_RATIO;
+    let mut lines: Vec<f64> = vec![];
+    for word in text.split_whitespace() {
+        let word_width = word.chars().count() as f64 * char_width;
+        match lines.last_mut() {
+            Some(line) if *line + char_width + word_width <= width => {
+                *line += char_width + word_width
+            }
+            _ => lines.push(word_width),
+        }
+    }
+    lines
+}
+
+/// Round to 0.1px without printing -0
+fn round(x: f64) -> f64 {
+    (x * 10.0).round() / 10.0 + 0.0
+}
@@ -0,0 +1,42 @@
+#[cfg(test)]
+mod tests {
+    use crate::lib::*;
+
+    #[test]
+    fn test_min_max_size() {
+        let page = render_html_with_css(
+            "<div><p>a</p><section></section><h1>b</h1></div>",
+            "p { max-width: 100px; margin: 0 auto } \
+             section { width: 200px; max-width: 0 } \
+             h1 { max-width: none; min-height: 50px; font-size: 10px }",
+        )
+        .unwrap();
+        assert_eq!(
+            page.render_object.layout(300.0).to_debug_boxes(),
+            r#"div x=0 y=0 width=300 height=69.2
+  p x=100 y=0 width=100 height=19.2
+    "a" x=100 y=0 width=8 height=19.2
+  section x=0 y=19.2 width=0 height=0
+  h1 x=0 y=19.2 width=300 height=50
+    "b" x=0 y=19.2 width=5 height=12
+"#
+        );
+    }
+
+    #[test]
+    fn test_layout_percentage() {
+        let page = render_html_with_css(
+            "<div><p>a</p></div>",
+            "div { width: 50%; padding-left: 10% } p { width: 50%; margin-left: 10% }",
+        )
+        .unwrap();
+        // p is relative to the content width of div, i.e. 150 - 30
+        assert_eq!(
+            page.render_object.layout(300.0).to_debug_boxes(),
+            r#"div x=0 y=0 width=150 height=19.2
+  p x=42 y=0 width=60 height=19.2
+    "a" x=42 y=0 width=8 height=19.2
+"#
+        );
+    }
+}
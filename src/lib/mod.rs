@@ -1,5 +1,6 @@
 pub mod cssom;
 pub mod dom;
+pub mod layout;
 pub mod render_tree;
 pub mod browser;
 
@@ -1,45 +1,330 @@
+mod test;
+
 use super::*;
+use std::fmt;
 
 #[derive(Debug, Clone)]
 pub struct RenderObject {
+    // the node without its children, which are rendered to `children`
     pub node: Node,
+    // declarations matched to the node
     pub styles: StyleMap,
+    // styles + values inherited from the parent
+    pub computed: StyleMap,
     pub children: Vec<RenderObject>,
+    pub(crate) config: RenderConfig,
+}
+
+/// Font sizes and viewport which lengths of the render tree are resolved with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderConfig {
+    // base of `rem`
+    pub root_font_size: f64,
+    // font size of the root element, which descendants inherit
+    pub default_font_size: f64,
+    // base of `%` where the containing block is not known, i.e. outside layout
+    pub viewport_width: f64,
+}
+
+/// Custom pass over the render tree by `RenderObject::accept`, e.g. collecting links.
+/// Methods which are not implemented do nothing. The element has tag name and attributes, but
+/// not children, which are visited as render objects
+pub trait RenderVisitor {
+    fn visit_element(&mut self, _object: &RenderObject, _element: &Element) {}
+    fn visit_text(&mut self, _object: &RenderObject, _text: &str) {}
+}
+
+/// DOM, styles and render tree of a HTML document, built by `render_html`
+#[derive(Debug)]
+pub struct Page {
+    pub dom: Node,
+    pub engine: StyleEngine,
+    pub render_object: RenderObject,
+}
+
+/// Error of `render_html`
+#[derive(Debug, PartialEq, Clone)]
+pub enum RenderError {
+    Html(HtmlParseError),
+    Css(CssParseError),
+    // the root element isn't rendered, e.g. `display: none`
+    NoRenderObject,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::Html(e) => write!(f, "cannot parse HTML: {}", e),
+            RenderError::Css(e) => write!(f, "cannot parse stylesheet: {}", e),
+            RenderError::NoRenderObject => write!(f, "the root element is not rendered"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<HtmlParseError> for RenderError {
+    fn from(e: HtmlParseError) -> Self {
+        RenderError::Html(e)
+    }
+}
+
+impl From<CssParseError> for RenderError {
+    fn from(e: CssParseError) -> Self {
+        RenderError::Css(e)
+    }
+}
+
+/// Parse HTML and its <style> elements, and build the render tree
+pub fn render_html(html: &str) -> Result<Page, RenderError> {
+    let dom = DocumentObjectParser::new(html).parse()?;
+    let css = dom.extract_styles();
+    Page::new(dom, &css)
+}
+
+/// Same as `render_html`, but styled by the given CSS instead of <style> elements
+pub fn render_html_with_css(html: &str, css: &str) -> Result<Page, RenderError> {
+    let dom = DocumentObjectParser::new(html).parse()?;
+    Page::new(dom, css)
+}
+
+impl Page {
+    fn new(dom: Node, css: &str) -> Result<Self, RenderError> {
+        let stylesheet = StyleSheetParser::new(css).parse()?;
+        let engine = StyleEngine::new(stylesheet);
+        let render_object =
+            RenderObject::build(&dom, &engine).ok_or(RenderError::NoRenderObject)?;
+        Ok(Self {
+            dom,
+            engine,
+            render_object,
+        })
+    }
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            root_font_size: DEFAULT_FONT_SIZE,
+            default_font_size: DEFAULT_FONT_SIZE,
+            viewport_width: DEFAULT_VIEWPORT_WIDTH,
+        }
+    }
 }
 
 impl RenderObject {
-    pub fn build(node: Node, stylesheet: StyleSheet) -> Option<Self> {
+    pub fn build(node: &Node, engine: &StyleEngine) -> Option<Self> {
+        Self::build_with_config(node, engine, RenderConfig::default())
+    }
+
+    /// Same as `build`, but lengths are resolved with the given font sizes and viewport
+    ///
+    /// e.g. RenderConfig { root_font_size: 20.0, default_font_size: 20.0, ..Default::default() }
+    /// scales the page by 1.25
+    pub fn build_with_config(
+        node: &Node,
+        engine: &StyleEngine,
+        config: RenderConfig,
+    ) -> Option<Self> {
+        let initial = initial_styles(&config);
+        Self::build_with_parent(node, engine, &config, &initial, None, &[], 0)
+    }
+
+    /// `parent_context`, and `siblings` with `index` of the node in them, are where the node is
+    /// in DOM, which combinators and structural pseudo-classes look at
+    fn build_with_parent(
+        node: &Node,
+        engine: &StyleEngine,
+        config: &RenderConfig,
+        parent: &StyleMap,
+        parent_context: Option<&MatchContext>,
+        siblings: &[Node],
+        index: usize,
+    ) -> Option<Self> {
         let mut children = Vec::new();
         let styles: StyleMap;
+        let computed: StyleMap;
         match node {
-            Node::Element(ref e) => {
-                if let ElementTagName::Meta | ElementTagName::Script = e.tag_name {
+            Node::Element(e) => {
+                // non-visual elements, whose contents (e.g. <style>) are read from DOM beforehand
+                if let ElementTagName::Head
+                | ElementTagName::Meta
+                | ElementTagName::Title
+                | ElementTagName::Script
+                | ElementTagName::Style
+                | ElementTagName::Link = e.tag_name
+                {
                     return None;
                 }
-                styles = stylesheet.get_styles(e);
+                let context = MatchContext {
+                    element: e,
+                    parent: parent_context,
+                    siblings,
+                    index,
+                };
+                // including inline style
+                styles = engine.get_styles(&context);
                 if let Some(DeclarationValue::Display(Display::None)) =
                     styles.get(&DeclarationProperty::Display)
                 {
                     return None;
                 }
-                for child in e.clone().children {
-                    if let Some(ch) = Self::build(child, stylesheet.clone()) {
+                computed = compute_styles(parent, &styles, config);
+                for (i, child) in e.children.iter().enumerate() {
+                    if let Some(ch) = Self::build_with_parent(
+                        child,
+                        engine,
+                        config,
+                        &computed,
+                        Some(&context),
+                        &e.children,
+                        i,
+                    ) {
                         children.push(ch)
                     }
                 }
             }
+            // comments and whitespace between tags are not rendered
+            Node::Comment(_) | Node::Doctype(_) => return None,
+            Node::Text(text) if text.trim().is_empty() => return None,
             _ => {
                 styles = StyleMap::new();
+                computed = compute_styles(parent, &styles, config);
             }
         }
+        // shallow copy, so that building doesn't copy the subtree at every depth
+        let node = match node {
+            Node::Element(e) => Node::Element(Element::new(
+                e.tag_name.clone(),
+                e.attributes.clone(),
+                vec![],
+            )),
+            _ => node.clone(),
+        };
         let render_object = Self {
             node,
             styles,
+            computed,
             children,
+            config: *config,
         };
         Some(render_object)
     }
 
+    /// Dump render tree with computed styles of each node, nested by indentation
+    ///
+    /// e.g. <div><p>Hello</p></div> with `div { background-color: #fdfdff } p { font-size: 2em }`
+    ///   div { background-color: #fdfdffff; color: #000000ff; font-size: 16px }
+    ///     p { color: #000000ff; font-size: 32px }
+    ///       "Hello" { color: #000000ff; font-size: 32px }
+    pub fn debug_computed(&self) -> String {
+        let mut s = String::new();
+        self.write_computed(0, &mut s);
+        s
+    }
+
+    fn write_computed(&self, depth: usize, s: &mut String) {
+        let mut properties = self
+            .computed
+            .iter()
+            .map(|(property, value)| format!("{}: {}", property, value))
+            .collect::<Vec<_>>();
+        properties.sort();
+        s.push_str(&format!(
+            "{}{} {{ {} }}\n",
+            "  ".repeat(depth),
+            self.label(),
+            properties.join("; ")
+        ));
+        for child in self.children.iter() {
+            child.write_computed(depth + 1, s);
+        }
+    }
+
+    /// Name of the node in debug output, e.g. div, "Hello"
+    pub(crate) fn label(&self) -> String {
+        match &self.node {
+            Node::Element(elem) => elem.tag_name.to_string(),
+            Node::Text(text) => format!("{:?}", text),
+            Node::Comment(comment) => format!("<!--{}-->", comment),
+            Node::Doctype(doctype) => format!("<!doctype {}>", doctype),
+            Node::Style(_) => "#style".to_string(),
+            Node::EndTag => "#end".to_string(),
+        }
+    }
+
+    /// Visible text of the subtree separated by single spaces. `display: none` subtrees are
+    /// already pruned from the render tree
+    ///
+    /// e.g. <div><h1>Title</h1><p>Hello   <b>world</b></p></div> → "Title Hello world"
+    pub fn text_content(&self) -> String {
+        let mut texts = vec![];
+        self.collect_texts(&mut texts);
+        texts
+            .iter()
+            .flat_map(|text| text.split_whitespace())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn collect_texts<'a>(&'a self, texts: &mut Vec<&'a str>) {
+        if let Node::Text(text) = &self.node {
+            texts.push(text);
+        }
+        for child in self.children.iter() {
+            child.collect_texts(texts);
+        }
+    }
+
+    /// Heading outline of the subtree for accessibility tools, i.e. level and text of h1 to h3
+    /// in document order
+    ///
+    /// e.g. <div><h1>Title</h1><p>Hello</p><h2>Usage</h2></div> → [(1, "Title"), (2, "Usage")]
+    pub fn outline(&self) -> Vec<(usize, String)> {
+        let mut headings = vec![];
+        self.collect_headings(&mut headings);
+        headings
+    }
+
+    fn collect_headings(&self, headings: &mut Vec<(usize, String)>) {
+        if let Node::Element(elem) = &self.node {
+            if let Some(level) = elem.tag_name.heading_level() {
+                headings.push((level, self.text_content()));
+                return;
+            }
+        }
+        for child in self.children.iter() {
+            child.collect_headings(headings);
+        }
+    }
+
+    /// Walk the subtree with the visitor in document order, a parent before its children
+    pub fn accept(&self, visitor: &mut impl RenderVisitor) {
+        match &self.node {
+            Node::Element(elem) => visitor.visit_element(self, elem),
+            Node::Text(text) => visitor.visit_text(self, text),
+            _ => {}
+        }
+        for child in self.children.iter() {
+            child.accept(visitor);
+        }
+    }
+
+    /// Styles of the object by the stylesheet at the viewport width, including rules in `@media`
+    /// which match it. Empty for text. The object is matched alone, so rules which need the
+    /// parent or siblings (e.g. `div > p`, `:first-child`) don't apply
+    ///
+    /// e.g. with `@media (max-width: 600px) { p { color: red } }`, p at 500.0 → { color: red },
+    /// and at 800.0 → {}
+    pub fn matches_media(&self, stylesheet: &StyleSheet, viewport_width: f64) -> StyleMap {
+        match &self.node {
+            Node::Element(elem) => {
+                stylesheet.get_styles_for_viewport(&MatchContext::new(elem), viewport_width)
+            }
+            _ => StyleMap::new(),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn get_display(&self) -> &Display {
         if let Some(s) = self.value(&DeclarationProperty::Display) {
@@ -51,35 +336,292 @@ impl RenderObject {
         &Display::Block
     }
 
+    /// Whether the object flows inline, i.e. text or an element with `display: inline(-block)`
+    pub fn is_inline(&self) -> bool {
+        match self.node {
+            Node::Text(_) => true,
+            Node::Element(_) => {
+                matches!(self.get_display(), Display::Inline | Display::InlineBlock)
+            }
+            _ => false,
+        }
+    }
+
+    /// Children grouped for layout. Consecutive inline children share a group as a line,
+    /// and a block child is a group by itself
+    pub fn child_groups(&self) -> Vec<Vec<&RenderObject>> {
+        let mut groups: Vec<Vec<&RenderObject>> = vec![];
+        for child in self.children.iter() {
+            match groups.last_mut() {
+                Some(group) if child.is_inline() && group.iter().all(|c| c.is_inline()) => {
+                    group.push(child)
+                }
+                _ => groups.push(vec![child]),
+            }
+        }
+        groups
+    }
+
+    /// Length of the property in px, 0 when not specified. See `Length::to_px` for units
     #[allow(dead_code)]
     pub fn get_length(&self, margin: &DeclarationProperty) -> f64 {
-        if let Some(l) = self.value(margin) {
-            return match l {
-                DeclarationValue::Length(length) => match length {
-                    Length::Actual(l, unit) => match unit {
-                        Unit::Px => *l as f64,
-                        Unit::Em => *l as f64 * 8.0,
-                        _ => *l as f64,
-                    },
-                    Length::Auto => 0.0,
-                },
-                _ => 0.0,
-            };
+        self.get_length_in(margin, self.config.viewport_width)
+    }
+
+    /// Same as `get_length`, but `%` is relative to the width of the containing block
+    pub fn get_length_in(&self, property: &DeclarationProperty, containing_width: f64) -> f64 {
+        match self.value(property) {
+            Some(DeclarationValue::Length(length)) => self.to_px_in(length, containing_width),
+            _ => 0.0,
+        }
+    }
+
+    /// Resolve the length to px with the font size of the object. `%` is relative to the viewport
+    /// width of the config, as the containing block is not known before layout
+    pub fn to_px(&self, length: &Length) -> f64 {
+        self.to_px_in(length, self.config.viewport_width)
+    }
+
+    /// Same as `to_px`, but `%` is relative to the width of the containing block, e.g. in layout
+    pub fn to_px_in(&self, length: &Length, containing_width: f64) -> f64 {
+        length.to_px(
+            self.get_font_size(),
+            self.config.root_font_size,
+            containing_width,
+        )
+    }
+
+    /// Computed font size in px
+    pub fn get_font_size(&self) -> f64 {
+        match self.value(&DeclarationProperty::FontSize) {
+            Some(DeclarationValue::Length(Length::Actual(size, Unit::Px))) => *size as f64,
+            _ => self.config.default_font_size,
+        }
+    }
+
+    /// Computed font weight, 400 when not specified
+    pub fn get_font_weight(&self) -> u16 {
+        match self.value(&DeclarationProperty::FontWeight) {
+            Some(DeclarationValue::FontWeight(weight)) => *weight,
+            _ => 400,
+        }
+    }
+
+    /// Computed text-align, left when not specified
+    pub fn get_text_align(&self) -> &TextAlign {
+        match self.value(&DeclarationProperty::TextAlign) {
+            Some(DeclarationValue::TextAlign(text_align)) => text_align,
+            _ => &TextAlign::Left,
+        }
+    }
+
+    /// Opacity of the object itself in 0..=1, 1 when not specified. It is not inherited,
+    /// but the faded object fades its descendants too
+    pub fn get_opacity(&self) -> f64 {
+        match self.value(&DeclarationProperty::Opacity) {
+            Some(DeclarationValue::Number(opacity)) => *opacity as f64,
+            _ => 1.0,
+        }
+    }
+
+    /// Computed flex-direction, row when not specified
+    pub fn get_flex_direction(&self) -> &FlexDirection {
+        match self.value(&DeclarationProperty::FlexDirection) {
+            Some(DeclarationValue::FlexDirection(direction)) => direction,
+            _ => &FlexDirection::Row,
+        }
+    }
+
+    /// Computed justify-content, flex-start when not specified
+    pub fn get_justify_content(&self) -> &JustifyContent {
+        match self.value(&DeclarationProperty::JustifyContent) {
+            Some(DeclarationValue::JustifyContent(justify_content)) => justify_content,
+            _ => &JustifyContent::FlexStart,
         }
-        0.0
     }
 
+    /// flex-grow from `flex`, None when not specified
+    pub fn get_flex_grow(&self) -> Option<f64> {
+        match self.value(&DeclarationProperty::Flex) {
+            Some(DeclarationValue::Number(grow)) => Some(*grow as f64),
+            _ => None,
+        }
+    }
+
+    /// Computed grid-template-columns, empty when not specified
+    pub fn get_grid_template_columns(&self) -> &[TrackSize] {
+        match self.value(&DeclarationProperty::GridTemplateColumns) {
+            Some(DeclarationValue::TrackList(tracks)) => tracks,
+            _ => &[],
+        }
+    }
+
+    /// Children placed in a grid row by row, as many in a row as the columns.
+    /// Children make a single column without grid-template-columns
+    pub fn grid_rows(&self) -> Vec<&[RenderObject]> {
+        let columns = self.get_grid_template_columns().len().max(1);
+        self.children.chunks(columns).collect()
+    }
+
+    /// Line height in px for the given font size, None when `normal`
+    pub fn get_line_height(&self, font_size: f64) -> Option<f64> {
+        match self.value(&DeclarationProperty::LineHeight) {
+            Some(DeclarationValue::LineHeight(line_height)) => match line_height {
+                LineHeight::Normal => None,
+                LineHeight::Number(x) => Some(*x as f64 * font_size),
+                LineHeight::Length(Length::Auto) => None,
+                // percentage is relative to the font size
+                LineHeight::Length(length) => {
+                    Some(length.to_px(font_size, self.config.root_font_size, font_size))
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Width clamped by min-width/max-width, None when it is auto
     #[allow(dead_code)]
     pub fn get_width(&self) -> Option<f64> {
-        let width = self.get_length(&DeclarationProperty::Width);
-        if width != 0.0 {
-            return Some(width);
+        self.get_width_in(self.config.viewport_width)
+    }
+
+    /// Same as `get_width`, but `%` is relative to the width of the containing block
+    pub fn get_width_in(&self, containing_width: f64) -> Option<f64> {
+        self.get_specified_length(&DeclarationProperty::Width, containing_width)
+            .map(|width| self.clamp_width(width, containing_width))
+    }
+
+    /// Height clamped by min-height/max-height, None when it is auto. `%` is relative to the
+    /// height of the containing block, which isn't known before layout, so `%` height is auto
+    pub fn get_height(&self) -> Option<f64> {
+        self.get_definite_length(&DeclarationProperty::Height)
+            .map(|height| self.clamp_height(height))
+    }
+
+    /// Clamp a used width, e.g. the width of an auto-width block, by min-width/max-width.
+    /// `%` of them is relative to the width of the containing block
+    pub fn clamp_width(&self, width: f64, containing_width: f64) -> f64 {
+        self.clamp_length(
+            width,
+            &DeclarationProperty::MinWidth,
+            &DeclarationProperty::MaxWidth,
+            containing_width,
+        )
+    }
+
+    /// Clamp a used height, e.g. the height of the content, by min-height/max-height.
+    /// `%` of them doesn't limit the height, same as `%` height is auto
+    pub fn clamp_height(&self, height: f64) -> f64 {
+        let height = match self.get_definite_length(&DeclarationProperty::MaxHeight) {
+            Some(max) => height.min(max),
+            None => height,
+        };
+        height.max(
+            self.get_definite_length(&DeclarationProperty::MinHeight)
+                .unwrap_or(0.0),
+        )
+    }
+
+    /// Apply max first and then min, so min wins when they conflict as in CSS.
+    /// `max-width: none` doesn't limit, but `max-width: 0` does
+    fn clamp_length(
+        &self,
+        length: f64,
+        min: &DeclarationProperty,
+        max: &DeclarationProperty,
+        percent_base: f64,
+    ) -> f64 {
+        let length = match self.get_specified_length(max, percent_base) {
+            Some(max) => length.min(max),
+            None => length,
+        };
+        length.max(self.get_length_in(min, percent_base))
+    }
+
+    /// Same as `get_specified_length`, but None also when it is `%`
+    fn get_definite_length(&self, property: &DeclarationProperty) -> Option<f64> {
+        match self.value(property) {
+            Some(DeclarationValue::Length(Length::Actual(_, Unit::Pct))) => None,
+            _ => self.get_specified_length(property, 0.0),
+        }
+    }
+
+    /// Length of the property in px, None when it is not specified or a keyword, e.g. auto, none
+    fn get_specified_length(
+        &self,
+        property: &DeclarationProperty,
+        percent_base: f64,
+    ) -> Option<f64> {
+        match self.value(property) {
+            Some(DeclarationValue::Length(length @ Length::Actual(..))) => {
+                Some(self.to_px_in(length, percent_base))
+            }
+            _ => None,
+        }
+    }
+
+    /// Border radius in px. `em` is resolved same as other lengths.
+    /// Only the top-left corner is used for all corners for now
+    pub fn get_border_radius(&self) -> Option<f64> {
+        let radius = self.get_length(&DeclarationProperty::BorderTopLeftRadius);
+        if radius != 0.0 {
+            return Some(radius);
         }
         None
     }
 
+    /// Computed value of the property, i.e. including inherited one
     #[allow(dead_code)]
     pub fn value(&self, name: &DeclarationProperty) -> Option<&DeclarationValue> {
-        self.styles.get(name)
+        self.computed.get(name)
+    }
+}
+
+const DEFAULT_FONT_SIZE: f64 = 16.0;
+
+// width of the browser window
+pub(crate) const DEFAULT_VIEWPORT_WIDTH: f64 = 700.0;
+
+/// Initial values of inherited properties, which the root element inherits
+fn initial_styles(config: &RenderConfig) -> StyleMap {
+    let mut styles = StyleMap::new();
+    styles.insert(
+        DeclarationProperty::Color,
+        DeclarationValue::Color(Color::new(0x00, 0x00, 0x00, 0xff)),
+    );
+    styles.insert(
+        DeclarationProperty::FontSize,
+        DeclarationValue::Length(Length::Actual(config.default_font_size as f32, Unit::Px)),
+    );
+    styles
+}
+
+/// Inherited properties of the parent overridden by the node's own styles
+fn compute_styles(parent: &StyleMap, styles: &StyleMap, config: &RenderConfig) -> StyleMap {
+    let mut computed = parent
+        .iter()
+        .filter(|(property, _)| property.is_inherited())
+        .map(|(property, value)| (property.clone(), value.clone()))
+        .collect::<StyleMap>();
+    computed.extend(styles.iter().map(|(p, v)| (p.clone(), v.clone())));
+    if let Some(DeclarationValue::Length(size)) = styles.get(&DeclarationProperty::FontSize) {
+        let font_size = resolve_font_size(parent, size, config);
+        computed.insert(
+            DeclarationProperty::FontSize,
+            DeclarationValue::Length(Length::Actual(font_size as f32, Unit::Px)),
+        );
+    }
+    computed
+}
+
+/// Resolve `font-size` to px. `em` is relative to the font size of the parent
+fn resolve_font_size(parent: &StyleMap, size: &Length, config: &RenderConfig) -> f64 {
+    let parent_size = match parent.get(&DeclarationProperty::FontSize) {
+        Some(DeclarationValue::Length(Length::Actual(size, Unit::Px))) => *size as f64,
+        _ => config.default_font_size,
+    };
+    match size {
+        Length::Auto => parent_size,
+        _ => size.to_px(parent_size, config.root_font_size, parent_size),
     }
 }
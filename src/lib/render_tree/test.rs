@@ -0,0 +1,404 @@
+#[cfg(test)]
+mod tests {
+    use crate::lib::*;
+
+    #[test]
+    fn test_render_deep_document() {
+        let depth = 500;
+        let html = format!("{}deep{}", "<div>".repeat(depth), "</div>".repeat(depth));
+        let page = render_html_with_css(&html, "div { color: red }").unwrap();
+        let mut object = &page.render_object;
+        for _ in 1..depth {
+            // the copy of the element doesn't keep the subtree, which is in children
+            assert!(matches!(&object.node, Node::Element(e) if e.children.is_empty()));
+            assert_eq!(object.children.len(), 1);
+            object = &object.children[0];
+        }
+        assert_eq!(object.label(), "div");
+        assert_eq!(object.children[0].label(), "\"deep\"");
+        assert_eq!(
+            object.children[0].value(&DeclarationProperty::Color),
+            Some(&DeclarationValue::Color(Color::from_name("red").unwrap()))
+        );
+        assert_eq!(page.render_object.text_content(), "deep");
+    }
+
+    #[test]
+    fn test_debug_computed() {
+        let page = render_html_with_css(
+            "<div><p>Hello</p></div>",
+            "div { background-color: #fdfdff } p { font-size: 2em }",
+        )
+        .unwrap();
+        assert_eq!(
+            page.render_object.debug_computed(),
+            r#"div { background-color: #fdfdffff; color: #000000ff; font-size: 16px }
+  p { color: #000000ff; font-size: 32px }
+    "Hello" { color: #000000ff; font-size: 32px }
+"#
+        );
+    }
+
+    #[test]
+    fn test_percentage_of_viewport() {
+        let dom = DocumentObjectParser::new("<div><p>a</p></div>")
+            .parse()
+            .unwrap();
+        let stylesheet = StyleSheetParser::new("p { width: 50%; padding-left: 10% }")
+            .parse()
+            .unwrap();
+        let config = RenderConfig {
+            viewport_width: 300.0,
+            ..Default::default()
+        };
+        let object =
+            RenderObject::build_with_config(&dom, &StyleEngine::new(stylesheet), config).unwrap();
+        assert_eq!(object.children[0].get_width(), Some(150.0));
+        assert_eq!(
+            object.children[0].get_length(&DeclarationProperty::PaddingLeft),
+            30.0
+        );
+    }
+
+    #[test]
+    fn test_border_radius() {
+        let page = render_html_with_css(
+            "<div><p>a</p><span>b</span></div>",
+            "div { font-size: 20px; border-radius: 0.5em } p { border-radius: 4px 8px }",
+        )
+        .unwrap();
+        let object = &page.render_object;
+        // em is relative to the font size of the element
+        assert_eq!(object.get_border_radius(), Some(10.0));
+        // the top-left corner
+        assert_eq!(object.children[0].get_border_radius(), Some(4.0));
+        assert_eq!(object.children[1].get_border_radius(), None);
+    }
+
+    #[test]
+    fn test_inline_style() {
+        let page = render_html_with_css(
+            r#"<div><p class="note" style="color: #f00; margin-left: 4px">a</p></div>"#,
+            "p.note { color: blue; margin-right: 2px }",
+        )
+        .unwrap();
+        let object = &page.render_object.children[0];
+        // inline style wins over the rule, and declarations of both apply
+        assert_eq!(
+            object.value(&DeclarationProperty::Color),
+            Some(&DeclarationValue::Color(Color::new(0xff, 0x00, 0x00, 0xff)))
+        );
+        assert_eq!(object.get_length(&DeclarationProperty::MarginLeft), 4.0);
+        assert_eq!(object.get_length(&DeclarationProperty::MarginRight), 2.0);
+    }
+
+    #[test]
+    fn test_height() {
+        let page = render_html_with_css(
+            "<div><div>a</div><p>b</p><section>c</section></div>",
+            "div { height: 200px } p { font-size: 10px; height: 2em; max-height: 50% } \
+             section { height: 50%; min-height: 10% }",
+        )
+        .unwrap();
+        assert_eq!(page.render_object.get_height(), Some(200.0));
+        let children = &page.render_object.children;
+        assert_eq!(children[0].get_height(), Some(200.0));
+        // em is relative to the font size, and % of max-height doesn't limit
+        assert_eq!(children[1].get_height(), Some(20.0));
+        // % is auto as the height of the containing block isn't known
+        assert_eq!(children[2].get_height(), None);
+        assert_eq!(children[2].clamp_height(5.0), 5.0);
+    }
+
+    #[test]
+    fn test_line_height() {
+        let page = render_html_with_css(
+            "<div><p>a</p><h1>b</h1><section>c</section></div>",
+            "p { line-height: 24px } h1 { font-size: 20px; line-height: 1.5 } \
+             section { line-height: normal }",
+        )
+        .unwrap();
+        let children = &page.render_object.children;
+        assert_eq!(children[0].get_line_height(16.0), Some(24.0));
+        // unitless line-height multiplies the font size
+        assert_eq!(children[1].get_line_height(20.0), Some(30.0));
+        assert_eq!(children[2].get_line_height(16.0), None);
+    }
+
+    #[test]
+    fn test_font_size() {
+        let page = render_html_with_css(
+            "<div><p>a</p><h1>b</h1></div>",
+            "p { font-size: 20px; margin-left: 1em } h1 { font-size: 2em }",
+        )
+        .unwrap();
+        let children = &page.render_object.children;
+        assert_eq!(page.render_object.get_font_size(), 16.0);
+        // the label of the text is sized by the font size of its parent, which is inherited
+        assert_eq!(children[0].get_font_size(), 20.0);
+        assert_eq!(children[0].children[0].get_font_size(), 20.0);
+        assert_eq!(
+            children[0].get_length(&DeclarationProperty::MarginLeft),
+            20.0
+        );
+        assert_eq!(children[1].get_font_size(), 32.0);
+    }
+
+    #[test]
+    fn test_skip_comments() {
+        let page = render_html_with_css(
+            "<div>\n  <h1>a</h1>\n  <!-- c -->\n  <p>b</p>\n</div>",
+            "h1 + p { margin-left: 1px }",
+        )
+        .unwrap();
+        let children = &page.render_object.children;
+        let labels = children.iter().map(|c| c.label()).collect::<Vec<_>>();
+        assert_eq!(labels, vec!["h1", "p"]);
+        // the comment between them doesn't break the adjacent sibling
+        assert_eq!(
+            children[1].get_length(&DeclarationProperty::MarginLeft),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_skip_head() {
+        fn collect_labels(object: &RenderObject, labels: &mut Vec<String>) {
+            labels.push(object.label());
+            for child in object.children.iter() {
+                collect_labels(child, labels);
+            }
+        }
+        let page = render_html(include_str!("../example.html")).unwrap();
+        let mut found = vec![];
+        collect_labels(&page.render_object, &mut found);
+        assert_eq!(found[..3], ["html", "body", "div"]);
+        for tag in ["head", "title", "meta", "style", "link", "script"] {
+            assert!(
+                !found.iter().any(|label| label == tag),
+                "{} is rendered",
+                tag
+            );
+        }
+        // the text of title is only in head
+        assert_eq!(
+            found
+                .iter()
+                .filter(|label| label.as_str() == "\"Example Domain\"")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_render_html_same_as_manual() {
+        let html = include_str!("../example.html");
+        let page = render_html(html).unwrap();
+
+        let dom = DocumentObjectParser::new(html).parse().unwrap();
+        let stylesheet = StyleSheetParser::new(&dom.extract_styles())
+            .parse()
+            .unwrap();
+        let object = RenderObject::build(&dom, &StyleEngine::new(stylesheet)).unwrap();
+        assert_eq!(page.dom, dom);
+        assert_eq!(format!("{:?}", page.render_object), format!("{:?}", object));
+    }
+
+    #[test]
+    fn test_render_html_error() {
+        assert!(matches!(
+            render_html("<!-- no root element -->"),
+            Err(RenderError::Html(_))
+        ));
+        assert!(matches!(
+            render_html_with_css("<div>a</div>", "div { color: red"),
+            Err(RenderError::Css(_))
+        ));
+        assert!(matches!(
+            render_html_with_css("<div>a</div>", "div { display: none }"),
+            Err(RenderError::NoRenderObject)
+        ));
+    }
+
+    #[test]
+    fn test_text_content() {
+        let page = render_html(include_str!("../example.html")).unwrap();
+        let body = &page.render_object.children[0];
+        let div = &body.children[0];
+        assert_eq!(
+            div.children[1].text_content(),
+            "This domain is for use in illustrative examples in documents. You may use this \
+             domain in literature without prior coordination or asking for permission."
+        );
+        assert_eq!(div.children[2].text_content(), "More information...");
+        let page = render_html_with_css(
+            "<div><h1>Title</h1><p>Hello   <b>world</b><span>hidden</span></p></div>",
+            "span { display: none }",
+        )
+        .unwrap();
+        assert_eq!(page.render_object.text_content(), "Title Hello world");
+    }
+
+    #[test]
+    fn test_render_config() {
+        let dom = DocumentObjectParser::new("<div><p>a</p><h1>b</h1></div>")
+            .parse()
+            .unwrap();
+        let stylesheet = StyleSheetParser::new("p { margin-left: 1em; padding-left: 1rem }")
+            .parse()
+            .unwrap();
+        let config = RenderConfig {
+            root_font_size: 20.0,
+            default_font_size: 10.0,
+            ..Default::default()
+        };
+        let object =
+            RenderObject::build_with_config(&dom, &StyleEngine::new(stylesheet), config).unwrap();
+        let p = &object.children[0];
+        // em is relative to the font size inherited from the default, and rem to the root
+        assert_eq!(p.get_length(&DeclarationProperty::MarginLeft), 10.0);
+        assert_eq!(p.get_length(&DeclarationProperty::PaddingLeft), 20.0);
+        // h1 { font-size: 2em } of the user agent
+        assert_eq!(object.children[1].get_font_size(), 20.0);
+    }
+
+    #[test]
+    fn test_grid_rows() {
+        let page = render_html_with_css(
+            "<div><p>a</p><p>b</p><p>c</p><p>d</p></div>",
+            "div { display: grid; grid-template-columns: 100px 1fr }",
+        )
+        .unwrap();
+        let rows = page
+            .render_object
+            .grid_rows()
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| cell.text_content())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        // row-major 2x2 grid
+        assert_eq!(rows, vec![vec!["a", "b"], vec!["c", "d"]]);
+    }
+
+    #[test]
+    fn test_outline() {
+        let page = render_html(include_str!("../example.html")).unwrap();
+        assert_eq!(
+            page.render_object.outline(),
+            vec![(1, "Example Domain".to_string())]
+        );
+        let page = render_html(
+            "<main><h1>A</h1><p>x</p><section><h2>B <b>1</b></h2><h3>C</h3></section><h2>D</h2></main>",
+        )
+        .unwrap();
+        assert_eq!(
+            page.render_object.outline(),
+            vec![
+                (1, "A".to_string()),
+                (2, "B 1".to_string()),
+                (3, "C".to_string()),
+                (2, "D".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_semantic_tags_display() {
+        let page = render_html(
+            "<div><section>a</section><nav>b</nav><header>c</header><footer>d</footer>\
+             <aside>e</aside><figure>f</figure><button>g</button></div>",
+        )
+        .unwrap();
+        let displays = page
+            .render_object
+            .children
+            .iter()
+            .map(|object| object.get_display().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            displays,
+            vec![
+                Display::Block,
+                Display::Block,
+                Display::Block,
+                Display::Block,
+                Display::Block,
+                Display::Block,
+                Display::InlineBlock,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_visitor() {
+        #[derive(Default)]
+        struct Stats {
+            labels: Vec<String>,
+            text_len: usize,
+        }
+        impl RenderVisitor for Stats {
+            fn visit_element(&mut self, object: &RenderObject, _element: &Element) {
+                self.labels.push(object.label());
+            }
+            fn visit_text(&mut self, _object: &RenderObject, text: &str) {
+                self.text_len += text.len();
+            }
+        }
+
+        let page = render_html(include_str!("../example.html")).unwrap();
+        let mut stats = Stats::default();
+        page.render_object.accept(&mut stats);
+        // parents are visited before their children, in document order
+        assert_eq!(
+            stats.labels,
+            vec!["html", "body", "div", "h1", "p", "p", "a"]
+        );
+        // the heading, the paragraph with its line break and the link
+        assert_eq!(stats.text_len, 185);
+    }
+
+    #[test]
+    fn test_style_media_attribute() {
+        let page = render_html(
+            r#"<html><head>
+            <style media="print">p { color: red }</style>
+            <style media="screen">h1 { color: green }</style>
+            </head><body><h1>a</h1><p>b</p></body></html>"#,
+        )
+        .unwrap();
+        let body = &page.render_object.children[0];
+        assert_eq!(
+            body.children[0].value(&DeclarationProperty::Color),
+            Some(&DeclarationValue::Color(Color::from_name("green").unwrap()))
+        );
+        // the print style is left out of the screen render
+        assert_eq!(body.children[1].label(), "p");
+        assert_ne!(
+            body.children[1].value(&DeclarationProperty::Color),
+            Some(&DeclarationValue::Color(Color::from_name("red").unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_matches_media() {
+        let css = "p { font-size: 16px } @media (max-width: 600px) { p { color: red } }";
+        let stylesheet = StyleSheetParser::new(css).parse().unwrap();
+        let page = render_html_with_css("<div><p>x</p></div>", css).unwrap();
+        let p = &page.render_object.children[0];
+        assert_eq!(p.label(), "p");
+        let red = DeclarationValue::Color(Color::from_name("red").unwrap());
+        assert_eq!(
+            p.matches_media(&stylesheet, 500.0)
+                .get(&DeclarationProperty::Color),
+            Some(&red)
+        );
+        let wide = p.matches_media(&stylesheet, 800.0);
+        assert_eq!(wide.get(&DeclarationProperty::Color), None);
+        assert!(wide.contains_key(&DeclarationProperty::FontSize));
+        // text has no styles of its own
+        assert!(p.children[0].matches_media(&stylesheet, 500.0).is_empty());
+    }
+}
@@ -1,9 +1,11 @@
 #![feature(box_patterns)]
 #![feature(box_syntax)]
-use lib::Browser;
 
 mod lib;
 
+// the engine is usable on its own, e.g. parsing and laying a page out without the window
+pub use lib::*;
+
 fn main() {
     Browser::new(String::from("https://example.com")).run()
 }